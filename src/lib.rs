@@ -0,0 +1,91 @@
+/*
+ * thegrep - Tar Heel egrep
+ *
+ * Author(s): Sarah Bost, Shannon Goad
+ * ONYEN(s): sbost99, sgoad13
+ *
+ * UNC Honor Pledge: I pledge I have received no unauthorized aid
+ * on this assignment. I further pledge not to distribute my solution
+ * to this code to anyone other than the course staff and partner.
+ */
+
+// the original NFA tests compare with true and false, and are kept as they were written
+#![cfg_attr(test, allow(clippy::bool_assert_comparison))]
+// AST, NFA and DFA are named the way the course named them, whether or not they are exported
+#![allow(clippy::upper_case_acronyms)]
+
+/*
+ * The pieces of thegrep. Programs that link it get Regex and the types that
+ * go with it, Pattern for building patterns in code, and the automata and
+ * syntax modules below. The states and fragments inside an NFA stay
+ * private, as does everything not listed here.
+ */
+pub(crate) mod lint;
+pub(crate) mod nfa;
+pub(crate) mod parser;
+mod regex;
+pub(crate) mod tokenizer;
+
+pub use self::parser::pattern::Pattern;
+pub use self::regex::captures::Captures;
+pub use self::regex::template::Template;
+pub use self::regex::{Match, Matches, Regex, RegexBuilder, Split, SplitN};
+
+/**
+ * Automata built from patterns, and questions about the whole set of
+ * strings they match: whether one pattern matches only strings another
+ * does too, or matches nothing, or everything.
+ */
+pub mod automata {
+    pub use crate::nfa::dfa::{is_empty, is_subset, is_universal, Sampler, Strings, Verdict, ALPHANUMERIC, DFA};
+    pub use crate::nfa::generate::{parse_alphabet, GenConfig, Generator, NearMisses};
+    pub use crate::nfa::helpers::{nfa_dot, nfa_dump};
+    pub use crate::nfa::NFA;
+}
+
+/**
+ * The tokenizer and parser, for programs that want a pattern's tree rather
+ * than a compiled Regex.
+ */
+pub mod syntax {
+    pub use crate::parser::{Parser, SpanTree, AST, DEFAULT_NEST_LIMIT};
+    pub use crate::tokenizer::{Span, Token, Tokenizer};
+}
+
+/**
+ * What the command line tool in main.rs uses besides the modules above.
+ * This is not part of the library's API.
+ */
+#[doc(hidden)]
+pub mod cli {
+    pub use crate::lint::{lint, render};
+    pub use crate::nfa::learn::learn;
+    pub use crate::parser::explain::explain;
+    pub use crate::parser::helpers::{ast_json, ast_sexpr, ast_tree};
+}
+
+#[cfg(test)]
+mod public_paths {
+    use crate::automata::{is_empty, is_subset, is_universal, Verdict, NFA};
+    use crate::{Pattern, Regex};
+
+    #[test]
+    fn language_queries() {
+        let whitelist = NFA::from("admin|root").unwrap();
+        let blacklist = NFA::from("[a-z]+").unwrap();
+        assert!(is_subset(&whitelist, &blacklist).holds());
+        assert_eq!(is_subset(&blacklist, &whitelist), Verdict::No("a".to_string()));
+        assert_eq!(is_empty(&NFA::from("a&b").unwrap()), Verdict::Yes);
+        assert_eq!(is_universal(&NFA::from("~a").unwrap()), Verdict::No("a".to_string()));
+    }
+
+    #[test]
+    fn pattern_builder() {
+        let pattern = Pattern::literal("a.b").then(Pattern::any().star()).or(Pattern::literal("(x)"));
+        assert_eq!(pattern.to_string(), "a\\.b.*|\\(x\\)");
+        let nfa = pattern.compile();
+        assert!(nfa.accepts("a.bc"));
+        assert!(!nfa.accepts("axb"));
+        assert!(Regex::new(&pattern.to_string()).unwrap().is_match("(x)"));
+    }
+}
@@ -0,0 +1,295 @@
+use super::nfa::NFA;
+use super::parser::{Parser, SpanTree, AST};
+use super::tokenizer::{Span, Tokenizer};
+
+/*
+ * thegrep - Tar Heel egrep - Lint
+ *
+ * Checks patterns for sub-expressions that make backtracking matchers slow,
+ * so they can be caught before they go into configs shared with them.
+ */
+
+/**
+ * A problem found in a pattern, along with the span of the sub-expression
+ * responsible for it.
+ */
+#[derive(Debug, PartialEq)]
+pub struct Warning {
+    pub span: Span,
+    pub message: String,
+}
+
+/**
+ * Check a pattern as written, before any simplification, for:
+ * - repetitions of something that can match empty text, like (a*)*, which
+ *   can loop without consuming input
+ * - repetitions that can match the same text in exponentially many ways,
+ *   like (a|a)* or (a+)+
+ * - runs of three or more repetitions that can all match the same chars,
+ *   like .*a.*a.*, which take polynomial time to fail
+ * - patterns that match empty text, like a*|b, and so match every line
+ *
+ * The pattern is read in the extended syntax if extended is set, the way
+ * thegrep -x reads it. Warnings are ordered by where they start in the
+ * pattern.
+ */
+pub fn lint(pattern: &str, extended: bool) -> Result<Vec<Warning>, String> {
+    let tokens = if extended {
+        Tokenizer::extended(pattern)
+    } else {
+        Tokenizer::new(pattern)
+    };
+    let (ast, spans) = Parser::parse_spanned(tokens)?;
+    let nodes = nodes(&ast, &spans);
+    let text = |span: Span| &pattern[span.start..span.end];
+    let mut warnings: Vec<Warning> = vec![];
+
+    // children come before their parents, so each problem is reported once,
+    // at the innermost repetition that causes it
+    for (node, tree, _) in nodes.iter() {
+        match node {
+            AST::Closure(_) | AST::OneOrMore(_) => {}
+            _ => continue,
+        }
+        let span = tree.span;
+        if warnings
+            .iter()
+            .any(|w| span.start <= w.span.start && w.span.end <= span.end)
+        {
+            continue;
+        }
+
+        let nfa = NFA::from_ast(node);
+        let message = if nfa.has_epsilon_cycle() {
+            format!(
+                "`{}` repeats something that can match empty text, so it can loop without consuming input",
+                text(span)
+            )
+        } else if nfa.is_exponentially_ambiguous() {
+            format!(
+                "`{}` can match the same text in exponentially many ways",
+                text(span)
+            )
+        } else {
+            continue;
+        };
+        warnings.push(Warning { span, message });
+    }
+
+    for (node, tree, spine_tail) in nodes.iter() {
+        if let AST::Catenation(_, _) = node {
+            if !spine_tail {
+                warnings.extend(overlapping_repetitions(node, tree, &text));
+            }
+        }
+    }
+
+    if NFA::from_ast(&ast).matches_empty() {
+        warnings.push(Warning {
+            span: spans.span,
+            message: format!(
+                "`{}` matches empty text, so it matches every line",
+                text(spans.span)
+            ),
+        });
+    }
+
+    warnings.sort_by_key(|w| (w.span.start, w.span.end));
+    Ok(warnings)
+}
+
+/**
+ * Show a warning under the pattern with the offending span underlined.
+ */
+pub fn render(pattern: &str, warning: &Warning) -> String {
+    let before = pattern[..warning.span.start].chars().count();
+    let width = pattern[warning.span.start..warning.span.end].chars().count();
+    format!(
+        "warning: {}\n  {}\n  {}{}",
+        warning.message,
+        pattern,
+        " ".repeat(before),
+        "^".repeat(width.max(1))
+    )
+}
+
+/**
+ * Every node with its spans, children before parents, and whether it is
+ * the rhs of a catenation that is itself a catenation.
+ */
+fn nodes<'a>(ast: &'a AST, spans: &'a SpanTree) -> Vec<(&'a AST, &'a SpanTree, bool)> {
+    let mut order = vec![];
+    let mut stack = vec![(ast, spans, false)];
+    while let Some((node, tree, spine_tail)) = stack.pop() {
+        order.push((node, tree, spine_tail));
+        let is_cat = matches!(node, AST::Catenation(_, _));
+        for (i, (child, child_tree)) in node.children().into_iter().zip(&tree.children).enumerate() {
+            let tail = is_cat && i == 1 && matches!(child, AST::Catenation(_, _));
+            stack.push((child, child_tree, tail));
+        }
+    }
+    order.reverse();
+    order
+}
+
+/**
+ * Warn about runs of three or more repetitions in one catenation where
+ * each repetition, and everything between it and the next, can match some
+ * of the same chars: a backtracking matcher tries every way of dividing the
+ * text between them.
+ */
+fn overlapping_repetitions<'a>(
+    ast: &AST,
+    tree: &SpanTree,
+    text: &dyn Fn(Span) -> &'a str,
+) -> Vec<Warning> {
+    // the terms along the catenation's right-leaning spine
+    let mut terms = vec![];
+    let (mut node, mut tree) = (ast, tree);
+    while let AST::Catenation(lhs, rhs) = node {
+        terms.push((&**lhs, &tree.children[0]));
+        node = rhs;
+        tree = &tree.children[1];
+    }
+    terms.push((node, tree));
+
+    let repeats = |ast: &AST| matches!(ast, AST::Closure(_) | AST::OneOrMore(_));
+    let mut warnings = vec![];
+    let mut i = 0;
+    while i < terms.len() {
+        if !repeats(terms[i].0) {
+            i += 1;
+            continue;
+        }
+        let (mut last, mut count) = (i, 1);
+        for j in i + 1..terms.len() {
+            if !overlaps(&chars(terms[last].0), &chars(terms[j].0)) {
+                break;
+            }
+            if repeats(terms[j].0) {
+                last = j;
+                count += 1;
+            }
+        }
+        if count >= 3 {
+            let span = Span {
+                start: terms[i].1.span.start,
+                end: terms[last].1.span.end,
+            };
+            warnings.push(Warning {
+                span,
+                message: format!(
+                    "`{}` has {} repetitions that can match the same chars, so matching can take time proportional to the line length to the power {}",
+                    text(span),
+                    count,
+                    count
+                ),
+            });
+        }
+        i = last + 1;
+    }
+    warnings
+}
+
+/**
+ * The chars a pattern can consume, or None if it can consume any char.
+ */
+fn chars(ast: &AST) -> Option<Vec<(char, char)>> {
+    ast.synthesize(|node, children: Vec<Option<Vec<(char, char)>>>| match node {
+        AST::AnyChar | AST::Complement(_) => None,
+        AST::Char(c) => Some(vec![(*c, *c)]),
+        AST::Class(ranges) => Some(ranges.clone()),
+        _ => children
+            .into_iter()
+            .try_fold(vec![], |mut all, ranges| {
+                all.extend(ranges?);
+                Some(all)
+            }),
+    })
+}
+
+fn overlaps(lhs: &Option<Vec<(char, char)>>, rhs: &Option<Vec<(char, char)>>) -> bool {
+    match (lhs, rhs) {
+        (Some(lhs), Some(rhs)) => lhs
+            .iter()
+            .any(|(ls, le)| rhs.iter().any(|(rs, re)| ls <= re && rs <= le)),
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod lint_tests {
+    use super::*;
+
+    fn spans(pattern: &str) -> Vec<(usize, usize)> {
+        lint(pattern, false)
+            .unwrap()
+            .iter()
+            .map(|w| (w.span.start, w.span.end))
+            .collect()
+    }
+
+    #[test]
+    fn clean_patterns() {
+        assert!(spans("ERROR.*timeout").is_empty());
+        assert!(spans("(ab*c)*[a-z]+").is_empty());
+        assert!(spans("(a|b)*c").is_empty());
+        assert!(spans(".*foo.*").is_empty());
+    }
+
+    #[test]
+    fn epsilon_loops() {
+        assert_eq!(spans("x(a*)*y"), vec![(1, 6)]);
+        let warnings = lint("(b|a*)+", false).unwrap();
+        assert!(warnings[0].message.contains("empty text"));
+    }
+
+    #[test]
+    fn exponential() {
+        assert_eq!(spans("x(a|a)*y"), vec![(1, 7)]);
+        assert_eq!(spans("(a+)+"), vec![(0, 5)]);
+        // only the innermost offending repetition is reported, besides the
+        // outer one matching empty text
+        assert_eq!(spans("((a|a)*b)*"), vec![(0, 10), (1, 7)]);
+        let warnings = lint("((a|a)*b)*", false).unwrap();
+        assert_eq!(warnings[0].message, "`((a|a)*b)*` matches empty text, so it matches every line");
+    }
+
+    #[test]
+    fn polynomial() {
+        assert_eq!(spans("x.*a.*a.*"), vec![(1, 9)]);
+        // the only warning is that all three can match nothing at once
+        let warnings = lint("a*b*c*", false).unwrap();
+        assert_eq!(spans("a*b*c*"), vec![(0, 6)]);
+        assert_eq!(warnings[0].message, "`a*b*c*` matches empty text, so it matches every line");
+        assert_eq!(spans("a*a+a*"), vec![(0, 6)]);
+    }
+
+    #[test]
+    fn matches_empty() {
+        assert_eq!(spans("a*|b"), vec![(0, 4)]);
+        assert_eq!(spans(" (x|y*) "), vec![(1, 7)]);
+        assert!(spans("a+|b").is_empty());
+        let warnings = lint("a*", false).unwrap();
+        assert_eq!(warnings[0].message, "`a*` matches empty text, so it matches every line");
+    }
+
+    #[test]
+    fn extended() {
+        // a dash is only an operator in the extended syntax, where a*-b
+        // still matches empty text
+        assert!(spans("a*-b").is_empty());
+        let warnings = lint("a*-b", true).unwrap();
+        assert_eq!(warnings[0].message, "`a*-b` matches empty text, so it matches every line");
+        assert!(lint("a-", true).is_err());
+    }
+
+    #[test]
+    fn rendering() {
+        let warning = &lint("é(a|a)*", false).unwrap()[0];
+        assert_eq!(
+            render("é(a|a)*", warning),
+            "warning: `(a|a)*` can match the same text in exponentially many ways\n  é(a|a)*\n   ^^^^^^"
+        );
+    }
+}
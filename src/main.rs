@@ -1,5 +1,4 @@
-#[allow(unused)]
-/**
+/*
  * thegrep - Tar Heel egrep
  *
  * Author(s): Sarah Bost, Shannon Goad
@@ -14,79 +13,265 @@ extern crate structopt;
 use structopt::StructOpt;
 #[derive(Debug, StructOpt)]
 #[structopt(name = "thegrepc", about = "Tar Heel Egrep")]
-
 // setting up flags for parse and tokens
 struct Opt {
     #[structopt(short = "p", long = "parse")]
     parse: bool,
+    #[structopt(
+        long = "parse-format",
+        default_value = "debug",
+        raw(possible_values = r#"&["debug", "tree", "sexpr", "json"]"#),
+        help = "How --parse prints the parse tree"
+    )]
+    parse_format: String,
     #[structopt(short = "t", long = "tokens")]
     tokens: bool,
     #[structopt(short = "d", long = "dot")]
     dot: bool,
+    #[structopt(short = "n", long = "normalize", help = "Print the pattern in canonical form")]
+    normalize: bool,
+    #[structopt(
+        long = "expand",
+        help = "Print a pattern matching the same strings without &, ~ or -, worked out from its minimal DFA"
+    )]
+    expand: bool,
+    #[structopt(
+        long = "lint",
+        help = "Warn about parts of the pattern that make matching slow, exiting non-zero if any"
+    )]
+    lint: bool,
+    #[structopt(long = "explain", help = "Explain what the pattern matches in plain English")]
+    explain: bool,
+    #[structopt(
+        long = "equiv",
+        help = "Check whether two patterns match exactly the same strings, exiting non-zero if not"
+    )]
+    equiv: bool,
+    #[structopt(
+        long = "learn",
+        help = "Infer a pattern matching every line of the first file and no line of the second"
+    )]
+    learn: bool,
+    #[structopt(
+        short = "x",
+        long = "extended",
+        help = "Read a dash outside of a [class] as set difference, as in [a-z]+ - (if|else)"
+    )]
+    extended: bool,
     #[structopt(short = "g", long = "gen")]
     num: Option<i32>,
+    #[structopt(
+        long = "gen-negative",
+        help = "Generate this many lines the pattern finds no match in, mostly near misses"
+    )]
+    negative: Option<i32>,
+    #[structopt(
+        long = "gen-cover",
+        help = "Print matching strings that between them take every transition of the pattern's NFA"
+    )]
+    cover: bool,
+    #[structopt(
+        long = "length",
+        help = "With -g or --gen-negative, only generate strings of exactly this length, each as likely as any other"
+    )]
+    length: Option<usize>,
+    #[structopt(
+        long = "seed",
+        help = "With -g or --gen-negative, seed the generator so every run prints the same strings"
+    )]
+    seed: Option<u64>,
+    #[structopt(
+        long = "max-len",
+        help = "With -g or --gen-negative, generate no strings longer than this"
+    )]
+    max_len: Option<usize>,
+    #[structopt(
+        long = "alphabet",
+        help = "With -g or --gen-negative, the chars . stands for, written like the inside of a [class]",
+        default_value = "0-9A-Za-z"
+    )]
+    alphabet: String,
+    #[structopt(
+        long = "stop-prob",
+        help = "With -g or --gen-negative, the chance of leaving a * or + loop each time around it",
+        default_value = "0.5"
+    )]
+    stop_prob: f64,
+    #[structopt(
+        long = "unique",
+        help = "With -g or --gen-negative, never print the same string twice"
+    )]
+    unique: bool,
+    #[structopt(
+        long = "replace",
+        help = "Print every line, with each match in it replaced by this template, where $1 or ${name} is what a group matched, $0 the whole match, and \\U, \\L and \\E change case"
+    )]
+    replace: Option<String>,
+    #[structopt(
+        long = "split",
+        help = "Print the fields of every line, taking each match of the pattern as a delimiter between two"
+    )]
+    split: bool,
+    #[structopt(
+        long = "split-format",
+        default_value = "lines",
+        raw(possible_values = r#"&["lines", "tabs"]"#),
+        help = "How --split prints fields: each on its own line, or each line's as one tab-separated row"
+    )]
+    split_format: String,
     #[structopt(help = "FILES")]
     paths: Vec<String>,
 }
 
-use rand::prelude::*;
 use std::fs::File;
 use std::io;
 use std::io::BufRead;
-use std::io::Read;
 
 fn main() {
     let opt = Opt::from_args();
-    
+
     // if arguments are passed in read from file/paths otherwise evaluate input from std::in
     if opt.parse {
         // makes a parse tree of input
-        match Parser::parse(Tokenizer::new(&opt.paths[0])) {
-            Ok(statement) => {
-                println!("{:?}", statement);
-            }
-            Err(msg) => eprintln!("thegrep: {}", msg),
-        }
-        print!("\n");
+        print_parse(&opt.paths[0], &opt);
     } else if opt.tokens {
         println!("yes");
         // create a new tokenizer and cycle through tokens
-        let mut tokens = Tokenizer::new(&opt.paths[0]);
-        while let Some(token) = tokens.next() {
+        for token in tokenizer(&opt.paths[0], &opt) {
             println!("{:?}", token);
         }
-        print!("\n");
+        println!();
+    } else if opt.normalize {
+        // print the pattern back out with only the parens and escapes it needs
+        match Parser::parse(tokenizer(&opt.paths[0], &opt)) {
+            Ok(statement) => println!("{}", statement),
+            Err(msg) => {
+                eprintln!("thegrep: {}", msg);
+                std::process::exit(1);
+            }
+        }
+        std::process::exit(0);
+    } else if opt.explain {
+        // describe the pattern as written, before any simplification
+        match Parser::parse(tokenizer(&opt.paths[0], &opt)) {
+            Ok(statement) => println!("{}", explain(&statement)),
+            Err(msg) => {
+                eprintln!("thegrep: {}", msg);
+                std::process::exit(1);
+            }
+        }
+        std::process::exit(0);
+    } else if opt.lint {
+        // report sub-expressions that make backtracking matchers slow
+        match lint(&opt.paths[0], opt.extended) {
+            Ok(warnings) => {
+                for warning in &warnings {
+                    println!("{}", render(&opt.paths[0], warning));
+                }
+                std::process::exit(if warnings.is_empty() { 0 } else { 1 });
+            }
+            Err(msg) => {
+                eprintln!("thegrep: {}", msg);
+                std::process::exit(2);
+            }
+        }
+    } else if opt.equiv {
+        // compare the whole languages of two patterns with a product of their DFAs
+        if opt.paths.len() != 2 {
+            eprintln!("thegrep: --equiv takes exactly two patterns");
+            std::process::exit(2);
+        }
+        let (lhs, rhs) = match (
+            NFA::from_tokens(tokenizer(&opt.paths[0], &opt)),
+            NFA::from_tokens(tokenizer(&opt.paths[1], &opt)),
+        ) {
+            (Ok(lhs), Ok(rhs)) => (lhs, rhs),
+            (Err(msg), _) | (_, Err(msg)) => {
+                eprintln!("thegrep: {}", msg);
+                std::process::exit(2);
+            }
+        };
+        match lhs.distinguish(&rhs) {
+            None => {
+                println!("equivalent");
+                std::process::exit(0);
+            }
+            Some(witness) => {
+                let (matches, misses) = if lhs.to_dfa().accepts(&witness) {
+                    (&opt.paths[0], &opt.paths[1])
+                } else {
+                    (&opt.paths[1], &opt.paths[0])
+                };
+                println!("not equivalent: {:?} matches {:?} but {:?} does not", matches, witness, misses);
+                std::process::exit(1);
+            }
+        }
+    } else if opt.learn {
+        if opt.paths.len() != 2 {
+            eprintln!("thegrep: --learn takes a file of positive and a file of negative examples");
+            std::process::exit(2);
+        }
+        let (positives, negatives) = match (read_lines(&opt.paths[0]), read_lines(&opt.paths[1])) {
+            (Ok(positives), Ok(negatives)) => (positives, negatives),
+            (Err(e), _) | (_, Err(e)) => {
+                eprintln!("thegrep: {}", e);
+                std::process::exit(2);
+            }
+        };
+        match learn(&positives, &negatives) {
+            Ok(ast) => {
+                println!("{}", ast);
+                std::process::exit(0);
+            }
+            Err(msg) => {
+                eprintln!("thegrep: {}", msg);
+                std::process::exit(1);
+            }
+        }
+    } else if opt.expand {
+        println!("{}", compile(&opt.paths[0], &opt).to_ast());
+        std::process::exit(0);
     } else if opt.dot {
         // push output to dot nfa representation
-        let nfa = NFA::from(&opt.paths[0]).unwrap();
+        let nfa = compile(&opt.paths[0], &opt);
         println!("{}", nfa_dot(&nfa));
         std::process::exit(0);
     }
     if let Some(num) = opt.num {
         // if the number is less that 1, no random strings are generated and go straight to
         // standard in to read from input 
-        if (opt.paths.len() < 1) {
-            print_stdin(&opt);
+        if opt.paths.is_empty() {
+            if let Err(e) = print_stdin(&opt, Some(&matcher(&opt))) {
+                eprintln!("{}", e);
+            }
         }
 
-        //if user gives a number after gen flag, create nfa with the given regex 
-        let nfa = NFA::from(&opt.paths[0]).unwrap();
-
-        //call helper function in nfa that returns a random string that the nfa accepts
-        let mut expression_count = 0;
-
-        while expression_count < num { 
-            println!("{}", nfa.random_regex());
-
-            expression_count = expression_count + 1;
+        //if user gives a number after gen flag, create nfa with the given regex
+        let nfa = compile(&opt.paths[0], &opt);
+        let generator = gen_config(&opt).and_then(|config| nfa.generator(&config));
+        print_generated(generator, num);
+    }
+    if opt.cover {
+        for string in compile(&opt.paths[0], &opt).cover() {
+            println!("{}", string);
         }
+        std::process::exit(0);
+    }
+    if let Some(num) = opt.negative {
+        let nfa = compile(&opt.paths[0], &opt);
+        let near_misses = gen_config(&opt).and_then(|config| nfa.near_misses(&config));
+        print_generated(near_misses, num);
     }
 
+    // the pattern is compiled once, up front, and the same matcher tried on every line; -p and -t
+    // go on to parse or lex the lines themselves, and so have no use for one
+    let matcher = if opt.parse || opt.tokens { None } else { Some(matcher(&opt)) };
+
     //read from files if they are given at the command line, otherwise read from standard input
     let result = if opt.paths.len() > 1 {
-        print_files(&opt)
+        print_files(&opt, matcher.as_ref())
     } else {
-        print_stdin(&opt)
+        print_stdin(&opt, matcher.as_ref())
     };
 
     // print error if paths has error
@@ -96,73 +281,181 @@ fn main() {
 }
 
 // processes input and calls print function
-fn print_stdin(opt: &Opt) -> io::Result<()> {
+fn print_stdin(opt: &Opt, matcher: Option<&Matcher>) -> io::Result<()> {
     let stdin = io::stdin();
     let reader = stdin.lock();
-    print_lines(reader, opt)
+    print_lines(reader, opt, matcher)
 }
 
 // iterates through all paths/files and calls print function
-fn print_files(opt: &Opt) -> io::Result<()> {
-    let regex = opt.paths[0].to_string();
+fn read_lines(path: &str) -> io::Result<Vec<String>> {
+    io::BufReader::new(File::open(path)?).lines().collect()
+}
 
+fn print_files(opt: &Opt, matcher: Option<&Matcher>) -> io::Result<()> {
     for path in opt.paths.iter().skip(1) {
         // we skipped 1 because the first one is regex to match later, everything else is files
         let file = File::open(path)?;
-        let mut reader = io::BufReader::new(file);
-        print_lines(reader, opt)?;
+        let reader = io::BufReader::new(file);
+        print_lines(reader, opt, matcher)?;
     }
     Ok(())
 }
 
 // pushes all lines in a file onto string and calls eval function to call tokens/parser
-fn print_lines<R: BufRead>(reader: R, opt: &Opt) -> io::Result<()> {
+fn print_lines<R: BufRead>(reader: R, opt: &Opt, matcher: Option<&Matcher>) -> io::Result<()> {
     //call eval function to process tokens/parser
-    //rintln!("{}", opt.paths[0]);
     for line in reader.lines() {
-        eval(&line?, opt);
+        eval(&line?, opt, matcher);
     }
     Ok(())
 }
 
 // importing tokenizer and parser to use in main
-pub mod tokenizer;
-use self::tokenizer::Tokenizer;
-pub mod parser;
-use self::parser::Parser;
-pub mod nfa;
-use self::nfa::helpers::nfa_dot;
-use self::nfa::NFA;
-
-fn eval(input: &str, options: &Opt) {
-    
+use thegrep::syntax::Tokenizer;
+use thegrep::cli::explain;
+use thegrep::cli::{ast_json, ast_sexpr, ast_tree};
+use thegrep::syntax::Parser;
+use thegrep::cli::{lint, render};
+use thegrep::automata::nfa_dot;
+use thegrep::automata::{parse_alphabet, GenConfig};
+use thegrep::cli::learn;
+use thegrep::automata::NFA;
+use thegrep::{Regex, RegexBuilder, Template};
+
+// lexes a pattern in the syntax the options ask for
+fn tokenizer<'a>(pattern: &'a str, opt: &Opt) -> Tokenizer<'a> {
+    if opt.extended {
+        Tokenizer::extended(pattern)
+    } else {
+        Tokenizer::new(pattern)
+    }
+}
+
+// builds the NFA for a pattern, reporting a bad pattern as an error rather than a panic
+fn compile(pattern: &str, opt: &Opt) -> NFA {
+    match NFA::from_tokens(tokenizer(pattern, opt)) {
+        Ok(nfa) => nfa,
+        Err(msg) => {
+            eprintln!("thegrep: {}", msg);
+            std::process::exit(1);
+        }
+    }
+}
+
+// the compiled pattern lines are matched against, and what to print for each line
+struct Matcher {
+    regex: Regex,
+    // with --replace, the template matches are replaced by
+    template: Option<Template>,
+}
+
+// compiles the pattern lines are matched against, exiting with the error if it is bad
+fn matcher(opt: &Opt) -> Matcher {
+    let pattern = match opt.paths.first() {
+        Some(pattern) => pattern,
+        None => {
+            eprintln!("thegrep: no pattern given");
+            std::process::exit(2);
+        }
+    };
+    let regex = match RegexBuilder::new(pattern).extended(opt.extended).build() {
+        Ok(regex) => regex,
+        Err(msg) => {
+            eprintln!("thegrep: {}", msg);
+            std::process::exit(1);
+        }
+    };
+    if opt.split && opt.replace.is_some() {
+        eprintln!("thegrep: --split and --replace cannot be used together");
+        std::process::exit(2);
+    }
+    let template = opt.replace.as_ref().map(|template| Template::new(template));
+    if let Some(template) = &template {
+        if let Err(msg) = regex.check_template(template) {
+            eprintln!("thegrep: {}", msg);
+            std::process::exit(1);
+        }
+    }
+    Matcher { regex, template }
+}
+
+/**
+ * The -g settings given on the command line.
+ */
+fn gen_config(opt: &Opt) -> Result<GenConfig, String> {
+    Ok(GenConfig {
+        seed: opt.seed,
+        max_len: opt.max_len,
+        length: opt.length,
+        alphabet: parse_alphabet(&opt.alphabet)?,
+        stop: opt.stop_prob,
+        unique: opt.unique,
+    })
+}
+
+/**
+ * Print num generated strings and exit, or exit non-zero if the generator
+ * could not be set up.
+ */
+fn print_generated<I: Iterator<Item = String>>(generator: Result<I, String>, num: i32) -> ! {
+    let generator = match generator {
+        Ok(generator) => generator,
+        Err(msg) => {
+            eprintln!("thegrep: {}", msg);
+            std::process::exit(1);
+        }
+    };
+    let wanted = num.max(0) as usize;
+    let mut printed = 0;
+    for string in generator.take(wanted) {
+        println!("{}", string);
+        printed += 1;
+    }
+    if printed < wanted {
+        eprintln!("thegrep: only found {} different strings", printed);
+    }
+    std::process::exit(0);
+}
+
+// prints the parse tree of a pattern in one of the --parse-format formats
+fn print_parse(pattern: &str, opt: &Opt) {
+    match Parser::parse_spanned(tokenizer(pattern, opt)) {
+        Ok((statement, spans)) => match opt.parse_format.as_str() {
+            "tree" => print!("{}", ast_tree(&statement)),
+            "sexpr" => println!("{}", ast_sexpr(&statement)),
+            "json" => println!("{}", ast_json(&statement, &spans)),
+            _ => println!("{:?}", statement),
+        },
+        Err(msg) => eprintln!("thegrep: {}", msg),
+    }
+    println!();
+}
+
+fn eval(input: &str, options: &Opt, matcher: Option<&Matcher>) {
     if options.parse {
         // makes a parse tree of input
-        match Parser::parse(Tokenizer::new(input)) {
-            Ok(statement) => {
-                println!("{:?}", statement);
-            }
-            Err(msg) => eprintln!("thegrep: {}", msg),
-        }
-        print!("\n");
+        print_parse(input, options);
     } else if options.tokens {
-        //      println!("yes");
         // create a new tokenizer and cycle through tokens
-        let mut tokens = Tokenizer::new(input);
-        while let Some(token) = tokens.next() {
+        for token in tokenizer(input, options) {
             println!("{:?}", token);
         }
-        print!("\n");
+        println!();
     } else if options.dot {
         // push output to dot nfa representation
-        let nfa = NFA::from(input).unwrap();
+        let nfa = compile(input, options);
         println!("{}", nfa_dot(&nfa));
         std::process::exit(0);
-    }
-    else {
-        // no matter what options are chosen, make the NFA out of the given regex and test the input
-        let nfa = NFA::from(&options.paths[0]).unwrap();
-        if nfa.accepts(input) {
+    } else if let Some(matcher) = matcher {
+        if options.split {
+            let fields: Vec<&str> = matcher.regex.split(input).collect();
+            let separator = if options.split_format == "tabs" { "\t" } else { "\n" };
+            println!("{}", fields.join(separator));
+        } else if let Some(template) = &matcher.template {
+            // like sed, every line is printed, with whatever matches in it replaced
+            println!("{}", matcher.regex.replacen(input, 0, template));
+        } else if matcher.regex.is_match(input) {
             println!("{}", input);
         }
     }
@@ -1,4 +1,13 @@
+pub mod algebra;
+pub mod ambiguity;
+pub mod cover;
+pub mod dfa;
+pub mod eliminate;
+pub mod generate;
 pub mod helpers;
+pub mod learn;
+pub mod lengths;
+pub mod search;
 
 
 // Starter code for PS06 - thegrep
@@ -17,27 +26,29 @@ pub mod helpers;
 extern crate rand; 
 
 use self::State::*;
+use super::parser::literals::{required_literals, Literals};
+use super::parser::simplify::simplify;
 use super::parser::Parser;
 use super::parser::AST;
 use super::tokenizer::Tokenizer;
-use std::iter::Peekable;
-use std::ops::Add;
-use rand::prelude::*;
-use rand::distributions::Alphanumeric; 
-use std::iter; 
 
-/**
+/*
  * ===== Public API =====
  */
 
 /**
  * An NFA is represented by an arena Vec of States
- * and a start state.
+ * and a start state, along with the literal text any
+ * line it accepts must contain, how long that line
+ * must at least be, and the chars the pattern names.
  */
 #[derive(Debug)]
 pub struct NFA {
     start: StateId,
     states: Vec<State>,
+    literals: Literals,
+    shortest: usize,
+    named: Vec<(char, char)>,
 }
 
 impl NFA {
@@ -45,13 +56,28 @@ impl NFA {
      * Construct an NFA from a regular expression pattern.
      */
     pub fn from(regular_expression: &str) -> Result<NFA, String> {
+        NFA::from_tokens(Tokenizer::new(regular_expression))
+    }
+
+    /**
+     * Construct an NFA from a pattern being lexed by the given tokenizer, so
+     * that the extended syntax can be used.
+     */
+    pub fn from_tokens(tokens: Tokenizer) -> Result<NFA, String> {
+        // Parse the Abstract Syntax Tree of the Regular Expression, shrinking it where possible
+        let ast = simplify(Parser::parse(tokens)?);
+        Ok(NFA::from_ast(&ast))
+    }
+
+    /**
+     * Construct an NFA from an already parsed AST, exactly as it is given.
+     */
+    pub fn from_ast(ast: &AST) -> NFA {
         let mut nfa = NFA::new();
 
         let start = nfa.add_state(Start(None));
         nfa.start = start;
 
-        // Parse the Abstract Syntax Tree of the Regular Expression
-        let ast = &Parser::parse(Tokenizer::new(regular_expression))?;
         // The "body" of the NFA is made of the states between Start and End
         let body = nfa.gen_fragment(ast);
         nfa.join(nfa.start, body.start);
@@ -59,56 +85,14 @@ impl NFA {
         let end = nfa.add_state(End);
         nfa.join_fragment(&body, end);
 
-        Ok(nfa)
-    }
-
-    // generates a random String accepted by a regex
-    pub fn random_regex(&self) -> String {
-        let mut ran: String = "".to_string();  
-        self.random_regex_traverse(self.start, ran)
-    }
-
-    // helper function for generating a random String accepted by a regex, traverses the regex
-    pub fn random_regex_traverse( &self, position: StateId, mut ran: String) -> String {
-        
-        match &self.states[position] {
-            Start(state_id) => {
-                self.random_regex_traverse(state_id.unwrap(), ran) 
-
-            }
-            Split(lhs, rhs) => {
-     let mut rng = rand::thread_rng();
-     let direction: bool = rng.gen(); 
- 
-
-                if direction {
-                    return self.random_regex_traverse(lhs.unwrap(), ran); 
-                } else {
-                    return self.random_regex_traverse(rhs.unwrap(), ran); 
-                }
-            }
-            Match(character, state_id) => {
-                match &character {
-                    Char::Any => {
-                        //let rand_string = thread_rng().sample_iter(&Alphanumeric).take(30).collect();
-                        let mut random = rand::thread_rng(); 
-                        let chars: String = iter::repeat(())
-                            .map(|()| random.sample(Alphanumeric))
-                            .take(1)
-                            .collect();
-                        ran.push_str(&chars);
-                    }, 
-                    Char::Literal(ch)=> {
-                        ran.push(*ch);
-                    },
-                }
-                self.random_regex_traverse(state_id.unwrap(), ran) 
-            }
-            End => {
-                return ran; 
-            }
-        }
-    
+        nfa.literals = required_literals(ast);
+        nfa.shortest = nfa.min_length();
+        nfa.named = ast.synthesize(|node, children: Vec<Vec<(char, char)>>| match node {
+            AST::Char(c) => vec![(*c, *c)],
+            AST::Class(ranges) => ranges.clone(),
+            _ => children.concat(),
+        });
+        nfa
     }
 
     /**
@@ -116,143 +100,17 @@ impl NFA {
      * input is accepted by the input string.
      */
     pub fn accepts(&self, input: &str) -> bool {
-        // vector of chars formed from input so that we can iterate over them in accepts()
-        let input_chars = input.chars().collect();
-        // the final "false" here is a bool that will be useful later to see if the nfa has already
-        // been started being read in the input
-        
-        if input.len() == 0 {
-           return  self.traverse(&input_chars, 0, self.start, false);
+        // lines too short or without the text every match needs are rejected without
+        // simulating anything
+        if !self.could_match(input) {
+            return false;
         }
-        
-        let mut counter = 0;
-        for inp in &input_chars {
-            if self.traverse(&input_chars, counter, self.start, false) {
-                return true;
-            }
-            counter = counter + 1;
-        }
-        return false;
-    }
-
-    pub fn traverse(
-        &self,
-        chars: &Vec<char>,
-        chars_index: usize,
-        start_state_id: StateId,
-        has_started_nfa: bool,
-    ) -> bool {
-//        println!("{:?}", self.states); 
-        // we are matching by what state the nfa (regex) is on in our traversal
-        match &self.states[start_state_id] {
-            Start(state_id) => {
-                return self.traverse(&chars, chars_index, state_id.unwrap(), has_started_nfa)
-            }
-            // start state: just traverse to the next state by moving to next state_id
-            Split(lhs, rhs) => {
-                // split state: check both sides and traverse whichever one is necessary
-                if self.traverse(&chars, chars_index, lhs.unwrap(), has_started_nfa) {
-                    return true;
-                }
-                if self.traverse(&chars, chars_index, rhs.unwrap(), has_started_nfa) {
-                    return true;
-                }
-                return false;
-            }
-
-            Match(character, state_id) => {
-                // if we reach end of input chars before finding regex, return false
-                if chars_index == chars.len() {
-                    return false;
-                }
-                // see if the input char matches the NFA regex char that we expect
-                match character {
-                    Char::Literal(c) => {
-                        if c == &chars[chars_index] {
-                            return self.traverse(&chars, chars_index + 1, state_id.unwrap(), true);
-                        } else {
-                            if has_started_nfa {
-                                return self.traverse(&chars, chars_index, self.start, false);
-                            }
-                            return self.traverse(
-                                &chars,
-                                chars_index + 1,
-                                start_state_id,
-                                has_started_nfa,
-                            );
-                        }
-                    }
-                    Char::Any => {
-                        return self.traverse(
-                            &chars,
-                            chars_index + 1,
-                            state_id.unwrap(),
-                            has_started_nfa,
-                        );
-                    }
-                };
-            }
-            End => {
-                // end state: return true because it will only get here if it's a match!
-                return true;
-            }
-        };
+        let input_chars: Vec<char> = input.chars().collect();
+        self.find_at(&input_chars, 0).is_some()
     }
 }
 
-
-// operator overloading '+' for NFA
-impl Add for NFA {
-    type Output = Self;
-
-    // add takes in a lhs of self and an NFA rhs
-    fn add(self, rhs: NFA) -> NFA {
-        let mut concat = NFA::new();
-        // firstlength is a number we use for lots of upcoming arithmetic, based on the length of
-        // lhs
-        let firstlength = self.states.len() - 1;
-        let mut lhs_end = firstlength;
-        let mut rhs_start = firstlength - 1;
-        // this for loop loops through the lhs and adds those states to concat NFA to be returned
-        for i in 0..firstlength {
-            match &self.states[i] {
-                State::Start(n) => { 
-                    let start = concat.add_state(Start(Some(n.unwrap()))); 
-                    concat.start = start;
-                },
-                State::Match(c, n) => { concat.add_state(Match(*c, Some(n.unwrap()))); },
-                State::Split(n, m) => { concat.add_state(Split(Some(n.unwrap()), Some(m.unwrap()))); },
-                State::End => { lhs_end = i; },
-            };
-        }
-        // this for loop loops through the rhs states and adds them to concat 
-        for s in &rhs.states {
-            match s {
-                State::Start(n) => { rhs_start = n.unwrap() + rhs_start - 1; },
-                State::Match(c, n) => { concat.add_state(Match(*c, Some(n.unwrap() + firstlength - 1))); },
-                State::Split(n, m) => { concat.add_state(Split(Some(n.unwrap() + firstlength - 1), Some(m.unwrap() + firstlength - 1))); },
-                State::End => { concat.add_state(End); },
-            };
-        }
-        // loop back through concat and correct the stateid math where necessary
-        for i in 0..concat.states.len() {
-            match &concat.states[i] {
-                State::Match(c, n) => { 
-                    if n.unwrap() == lhs_end { concat.states[i] = Match(*c, Some(rhs_start + 1)); }
-                },
-                State::Split(n, m) => {
-                    if n.unwrap() == lhs_end && m.unwrap() == lhs_end { concat.states[i] = Split(Some(rhs_start), Some(rhs_start)); }
-                     else if m.unwrap() == lhs_end { concat.states[i] = Split(*n, Some(rhs_start + 1)); }
-                   //else if n.unwrap() == lhs_end { concat.states[i] = Split(Some(rhs_start + 1), *m); }
-                },
-                _ => { },
-            }
-        }
-        concat
-    }
-}   
-
-/**
+/*
  * ===== Internal API =====
  */
 type StateId = usize;
@@ -262,6 +120,7 @@ type StateId = usize;
  * - Start is starting state
  * - Match is a state with a single matching transition out
  * - Split is a state with two epsilon transitions out
+ * - Epsilon is a state with a single epsilon transition out
  * - End is the final accepting state
  */
 #[derive(Debug)]
@@ -269,6 +128,7 @@ enum State {
     Start(Option<StateId>),
     Match(Char, Option<StateId>),
     Split(Option<StateId>, Option<StateId>),
+    Epsilon(Option<StateId>),
     End,
 }
 
@@ -276,10 +136,38 @@ enum State {
  * Chars are the matching label of a non-epsilon edge in the
  * transition diagram representation of the NFA.
  */
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Clone)]
 enum Char {
     Literal(char),
     Any,
+    // sorted, non-overlapping inclusive ranges, as in AST::Class
+    Set(Vec<(char, char)>),
+}
+
+impl Char {
+    /**
+     * Whether an input char can be taken along an edge with this label.
+     */
+    fn matches(&self, input: char) -> bool {
+        match self {
+            Char::Literal(c) => *c == input,
+            Char::Any => true,
+            Char::Set(ranges) => ranges.iter().any(|(start, end)| *start <= input && input <= *end),
+        }
+    }
+
+    /**
+     * Whether some input char can be taken along edges with either label.
+     */
+    fn overlaps(&self, other: &Char) -> bool {
+        match (self, other) {
+            (Char::Any, _) | (_, Char::Any) => true,
+            (Char::Literal(c), other) | (other, Char::Literal(c)) => other.matches(*c),
+            (Char::Set(lhs), Char::Set(rhs)) => lhs
+                .iter()
+                .any(|(ls, le)| rhs.iter().any(|(rs, re)| ls <= re && rs <= le)),
+        }
+    }
 }
 
 /**
@@ -291,6 +179,9 @@ enum Char {
 struct Fragment {
     start: StateId,
     ends: Vec<StateId>,
+    // the lowest ID of the fragment's states; fragments are built bottom up,
+    // so while one is being built its states are all those from here on
+    first: StateId,
 }
 
 /**
@@ -304,6 +195,9 @@ impl NFA {
         NFA {
             states: vec![],
             start: 0,
+            literals: Literals::default(),
+            shortest: 0,
+            named: vec![],
         }
     }
 
@@ -319,71 +213,86 @@ impl NFA {
     /**
      * Given an AST node, this method returns a Fragment of the NFA
      * representing it and its children.
+     *
+     * The fragments are built bottom up by AST::synthesize, which walks the
+     * tree with an explicit stack, so long or deeply nested patterns cannot
+     * overflow the call stack.
      */
     fn gen_fragment(&mut self, ast: &AST) -> Fragment {
-        // creates fragments of an NFA based on what AST they are
-        match ast {
-            AST::AnyChar => {
-                let state = self.add_state(Match(Char::Any, None));
-                Fragment {
-                    start: state,
-                    ends: vec![state],
-                }
-            }
-            AST::Char(c) => {
-                let state = self.add_state(Match(Char::Literal(*c), None));
-                Fragment {
-                    start: state,
-                    ends: vec![state],
+        ast.synthesize(|node, mut children: Vec<Fragment>| {
+            // creates fragments of an NFA based on what AST they are
+            match node {
+                AST::AnyChar => self.gen_match(Char::Any),
+                AST::Char(c) => self.gen_match(Char::Literal(*c)),
+                AST::Class(ranges) => self.gen_match(Char::Set(ranges.clone())),
+                AST::Catenation(_, _) => {
+                    let fragment_rhs = children.pop().unwrap();
+                    let fragment_lhs = children.pop().unwrap();
+                    self.join_fragment(&fragment_lhs, fragment_rhs.start);
+
+                    Fragment {
+                        start: fragment_lhs.start,
+                        ends: fragment_rhs.ends,
+                        first: fragment_lhs.first,
+                    }
                 }
-            }
-            AST::Catenation(lhs, rhs) => {
-                let fragment_lhs = self.gen_fragment(lhs);
-                let fragment_rhs = self.gen_fragment(rhs);
-                self.join_fragment(&fragment_lhs, fragment_rhs.start);
-                self.join_fragment(&fragment_rhs, fragment_rhs.start);
-
-                Fragment {
-                    start: fragment_lhs.start,
-                    ends: fragment_rhs.ends,
+                AST::Alternation(_, _) => {
+                    let fragment_two = children.pop().unwrap();
+                    let fragment_one = children.pop().unwrap();
+                    let split_state =
+                        self.add_state(Split(Some(fragment_one.start), Some(fragment_two.start)));
+                    let mut v = vec![];
+                    v.extend(fragment_one.ends);
+                    v.extend(fragment_two.ends);
+                    Fragment {
+                        start: split_state,
+                        ends: v,
+                        first: fragment_one.first,
+                    }
                 }
-            }
-            AST::Alternation(one, two) => {
-                let fragment_one = self.gen_fragment(one);
-                let fragment_two = self.gen_fragment(two);
-                let split_state =
-                    self.add_state(Split(Some(fragment_one.start), Some(fragment_two.start)));
-                let mut v = vec![];
-                v.extend(fragment_one.ends);
-                v.extend(fragment_two.ends);
-                Fragment {
-                    start: split_state,
-                    ends: v,
+                AST::Closure(_) => {
+                    let fragment_ast = children.pop().unwrap();
+                    let split_state = self.add_state(Split(Some(fragment_ast.start), None));
+
+                    // every end of the body loops back around to the split state
+                    self.join_fragment(&fragment_ast, split_state);
+
+                    Fragment {
+                        start: split_state,
+                        ends: vec![split_state],
+                        first: fragment_ast.first,
+                    }
                 }
-            }
-            AST::Closure(ast) => {
-                let fragment_ast = self.gen_fragment(ast);
-                let split_state = self.add_state(Split(Some(fragment_ast.start), None));
-                self.join(split_state, fragment_ast.start);
-                self.join(fragment_ast.start, split_state);
-
-                Fragment {
-                    start: split_state,
-                    ends: vec![split_state],
+                AST::OneOrMore(_) => {
+                    let fragment_ast = children.pop().unwrap();
+                    let split_state = self.add_state(Split(Some(fragment_ast.start), None));
+
+                    //join fragment so the start points to to the split state
+                    self.join_fragment(&fragment_ast, split_state);
+
+                    Fragment {
+                        start: fragment_ast.start,
+                        ends: vec![split_state],
+                        first: fragment_ast.first,
+                    }
                 }
-            }
-            AST::OneOrMore(ast) => {
-                let fragment_ast = self.gen_fragment(&ast);
-                let split_state = self.add_state(Split(Some(fragment_ast.start), None));
-                
-                //join fragment so the start points to to the split state 
-                self.join_fragment(&fragment_ast, split_state);
-
-                Fragment {
-                    start: fragment_ast.start,
-                    ends: vec![split_state],
+                // there is no Thompson construction for these, so they go by way of a DFA
+                AST::Intersection(_, _) | AST::Difference(_, _) | AST::Complement(_) => {
+                    self.gen_dfa_fragment(node, children)
                 }
             }
+        })
+    }
+
+    /**
+     * A fragment of a single state matching one char.
+     */
+    fn gen_match(&mut self, c: Char) -> Fragment {
+        let state = self.add_state(Match(c, None));
+        Fragment {
+            start: state,
+            ends: vec![state],
+            first: state,
         }
     }
 
@@ -404,7 +313,7 @@ impl NFA {
      */
     fn join(&mut self, from: StateId, to: StateId) {
         match self.states[from] {
-            Start(ref mut next) => *next = Some(to),
+            Start(ref mut next) | Epsilon(ref mut next) => *next = Some(to),
             Match(_, ref mut next) => *next = Some(to),
             Split(_, ref mut next) => *next = Some(to),
             End => {}
@@ -566,6 +475,49 @@ mod public_api {
         assert_eq!(nfa.accepts("vacuum"), true);
     }
 
+    #[test]
+    fn test25() {
+        let nfa = NFA::from("x(ab)*y").unwrap();
+        assert!(nfa.accepts("xababy"));
+        assert!(nfa.accepts("xy"));
+        assert!(!nfa.accepts("xaay"));
+    }
+
+    #[test]
+    fn test26() {
+        let pattern = format!("{}a{}", "(".repeat(500), ")".repeat(500));
+        let nfa = NFA::from(&pattern).unwrap();
+        assert!(nfa.accepts("cat"));
+        assert!(NFA::from(&"(".repeat(100_000)).is_err());
+    }
+
+    #[test]
+    fn test27() {
+        let nfa = NFA::from(&"ab".repeat(2000)).unwrap();
+        assert!(nfa.accepts(&"ab".repeat(2000)));
+        assert!(!nfa.accepts("ab"));
+    }
+
+    #[test]
+    fn test28() {
+        let nfa = NFA::from("[a-c]x|(y*)*z").unwrap();
+        assert!(nfa.accepts("bx"));
+        assert!(!nfa.accepts("dx"));
+        assert!(nfa.accepts("yyz"));
+        assert!(!nfa.accepts("yyy"));
+    }
+
+    #[test]
+    fn test29() {
+        let nfa = NFA::from("a[b").unwrap();
+        assert!(nfa.accepts("xa[b"));
+        assert!(!nfa.accepts("ab"));
+        let nfa = NFA::from("x]|\\[y]").unwrap();
+        assert!(nfa.accepts("x]"));
+        assert!(nfa.accepts("[y]"));
+        assert!(!nfa.accepts("y"));
+    }
+
 }
 #[cfg(test)]
 mod op_overload_test {
@@ -0,0 +1,287 @@
+use super::State::*;
+use super::{Char, Fragment, NFA};
+use std::ops::{Add, BitAnd, BitOr};
+
+/*
+ * Building NFAs out of other NFAs, so patterns can be put together in
+ * code: nfa1 + nfa2 matches one then the other, nfa1 | nfa2 either,
+ * nfa1 & nfa2 both, and star, plus, optional and repeat repeat one.
+ *
+ * Each operand's states are copied into a fresh arena as a fragment, with
+ * its Start and End turned into epsilon transitions, and the fragments are
+ * wired together the same way gen_fragment wires those of a pattern.
+ */
+
+impl Add for NFA {
+    type Output = NFA;
+
+    fn add(self, rhs: NFA) -> NFA {
+        NFA::compose(&[&self, &rhs], |nfa| {
+            let lhs = nfa.absorb(&self);
+            let rhs = nfa.absorb(&rhs);
+            nfa.sequence(vec![lhs, rhs])
+        })
+    }
+}
+
+impl BitOr for NFA {
+    type Output = NFA;
+
+    fn bitor(self, rhs: NFA) -> NFA {
+        NFA::compose(&[&self, &rhs], |nfa| {
+            let lhs = nfa.absorb(&self);
+            let rhs = nfa.absorb(&rhs);
+            let split = nfa.add_state(Split(Some(lhs.start), Some(rhs.start)));
+            let mut ends = lhs.ends;
+            ends.extend(rhs.ends);
+            Fragment {
+                start: split,
+                ends,
+                first: lhs.first,
+            }
+        })
+    }
+}
+
+impl BitAnd for NFA {
+    type Output = NFA;
+
+    fn bitand(self, rhs: NFA) -> NFA {
+        self.intersection(&rhs)
+    }
+}
+
+impl NFA {
+    /**
+     * Zero or more repetitions, like a pattern's *.
+     */
+    pub fn star(&self) -> NFA {
+        NFA::compose(&[self], |nfa| {
+            let body = nfa.absorb(self);
+            nfa.closure(body, false)
+        })
+    }
+
+    /**
+     * One or more repetitions, like a pattern's +.
+     */
+    pub fn plus(&self) -> NFA {
+        NFA::compose(&[self], |nfa| {
+            let body = nfa.absorb(self);
+            nfa.closure(body, true)
+        })
+    }
+
+    /**
+     * Either the empty string or a match of this NFA.
+     */
+    pub fn optional(&self) -> NFA {
+        NFA::compose(&[self], |nfa| {
+            let body = nfa.absorb(self);
+            nfa.optional_fragment(body)
+        })
+    }
+
+    /**
+     * From min to max repetitions, or at least min if there is no max.
+     * Panics if max is less than min.
+     */
+    pub fn repeat(&self, min: usize, max: Option<usize>) -> NFA {
+        if let Some(max) = max {
+            assert!(min <= max, "repeat({}, Some({})) has max below min", min, max);
+        }
+        NFA::compose(&[self], |nfa| {
+            let mut pieces: Vec<Fragment> = (0..min).map(|_| nfa.absorb(self)).collect();
+            match max {
+                None => {
+                    let body = nfa.absorb(self);
+                    pieces.push(nfa.closure(body, false));
+                }
+                // each optional copy sits inside the one before, so no count is matched two ways
+                Some(max) => {
+                    let mut tail: Option<Fragment> = None;
+                    for _ in min..max {
+                        let copy = nfa.absorb(self);
+                        let copy = match tail {
+                            Some(tail) => nfa.sequence(vec![copy, tail]),
+                            None => copy,
+                        };
+                        tail = Some(nfa.optional_fragment(copy));
+                    }
+                    pieces.extend(tail);
+                }
+            }
+            nfa.sequence(pieces)
+        })
+    }
+
+    /**
+     * Any string with a match of this NFA somewhere in it, which is how
+     * thegrep matches lines.
+     */
+    pub fn anywhere(&self) -> NFA {
+        NFA::compose(&[self], |nfa| {
+            let before = nfa.any_chars();
+            let body = nfa.absorb(self);
+            let after = nfa.any_chars();
+            nfa.sequence(vec![before, body, after])
+        })
+    }
+
+    /**
+     * A new NFA with the fragment build makes between its Start and End.
+     * Nothing is known about the literals its matches need, but the chars
+     * the operands name are still named.
+     */
+    fn compose<F: FnOnce(&mut NFA) -> Fragment>(operands: &[&NFA], build: F) -> NFA {
+        let mut nfa = NFA::new();
+        nfa.start = nfa.add_state(Start(None));
+        let body = build(&mut nfa);
+        nfa.join(nfa.start, body.start);
+        let end = nfa.add_state(End);
+        nfa.join_fragment(&body, end);
+        nfa.shortest = nfa.min_length();
+        nfa.named = operands.iter().flat_map(|operand| operand.named.iter().cloned()).collect();
+        nfa
+    }
+
+    /**
+     * Copy another NFA's states to the end of the arena as a fragment. Its
+     * Start becomes an epsilon transition in, and its End one loose end.
+     */
+    fn absorb(&mut self, other: &NFA) -> Fragment {
+        let offset = self.states.len();
+        let shift = |id: usize| id + offset;
+        let mut ends = vec![];
+        for (id, state) in other.states.iter().enumerate() {
+            self.add_state(match state {
+                Start(next) | Epsilon(next) => Epsilon(next.map(shift)),
+                Match(c, next) => Match(c.clone(), next.map(shift)),
+                Split(lhs, rhs) => Split(lhs.map(shift), rhs.map(shift)),
+                End => {
+                    ends.push(shift(id));
+                    Epsilon(None)
+                }
+            });
+        }
+        Fragment {
+            start: shift(other.start),
+            ends,
+            first: offset,
+        }
+    }
+
+    /**
+     * Fragments one after another. No fragments at all match the empty
+     * string.
+     */
+    fn sequence(&mut self, pieces: Vec<Fragment>) -> Fragment {
+        let mut pieces = pieces.into_iter();
+        let mut whole = match pieces.next() {
+            Some(first) => first,
+            None => {
+                let empty = self.add_state(Epsilon(None));
+                return Fragment {
+                    start: empty,
+                    ends: vec![empty],
+                    first: empty,
+                };
+            }
+        };
+        for piece in pieces {
+            self.join_fragment(&whole, piece.start);
+            whole.ends = piece.ends;
+        }
+        whole
+    }
+
+    /**
+     * A loop around a fragment, entered at the split for *, or at the
+     * fragment itself for +.
+     */
+    fn closure(&mut self, body: Fragment, at_least_once: bool) -> Fragment {
+        let split = self.add_state(Split(Some(body.start), None));
+        self.join_fragment(&body, split);
+        Fragment {
+            start: if at_least_once { body.start } else { split },
+            ends: vec![split],
+            first: body.first,
+        }
+    }
+
+    // a .* of its own
+    fn any_chars(&mut self) -> Fragment {
+        let any = self.add_state(Match(Char::Any, None));
+        let body = Fragment {
+            start: any,
+            ends: vec![any],
+            first: any,
+        };
+        self.closure(body, false)
+    }
+
+    fn optional_fragment(&mut self, body: Fragment) -> Fragment {
+        let split = self.add_state(Split(Some(body.start), None));
+        let mut ends = body.ends;
+        ends.push(split);
+        Fragment {
+            start: split,
+            ends,
+            first: body.first,
+        }
+    }
+}
+
+#[cfg(test)]
+mod algebra_tests {
+    use super::*;
+
+    fn nfa(pattern: &str) -> NFA {
+        NFA::from(pattern).unwrap()
+    }
+
+    // the built NFA should match exactly what the pattern does
+    fn same(built: NFA, pattern: &str) {
+        assert_eq!(built.distinguish(&nfa(pattern)), None, "differs from {}", pattern);
+    }
+
+    #[test]
+    fn operators() {
+        same(nfa("a|b") + nfa("c|d"), "(a|b)(c|d)");
+        same(nfa("a*") + nfa("a"), "a+");
+        same(nfa("ab") | nfa("c*"), "ab|c*");
+        same(nfa("[a-z]+") & nfa("..."), "[a-z][a-z][a-z]");
+        same((nfa("a") + nfa("b")) | (nfa("c") + nfa("d") + nfa("e")), "ab|cde");
+    }
+
+    #[test]
+    fn repetitions() {
+        same(nfa("ab").star(), "(ab)*");
+        same(nfa("ab").plus(), "(ab)+");
+        same(nfa("ab").optional(), "ab|[]*");
+        same(nfa("a|bc").star().plus(), "(a|bc)*");
+    }
+
+    #[test]
+    fn repeat() {
+        same(nfa("ab").repeat(2, Some(2)), "abab");
+        same(nfa("a").repeat(1, Some(3)), "a|aa|aaa");
+        same(nfa("a").repeat(2, None), "aaa*");
+        same(nfa("x").repeat(0, Some(0)), "[]*");
+        same(nfa("a|b").repeat(0, Some(2)).plus(), "[ab]*");
+    }
+
+    #[test]
+    fn matching_lines() {
+        let phone = nfa("[0-9]").repeat(3, Some(3)) + nfa("-") + nfa("[0-9]").repeat(4, Some(4));
+        assert!(phone.accepts("call 555-1234 now"));
+        assert!(!phone.accepts("call 55-1234 now"));
+        assert_eq!(phone.min_length(), 8);
+    }
+
+    #[test]
+    #[should_panic]
+    fn backwards_repeat() {
+        nfa("a").repeat(3, Some(1));
+    }
+}
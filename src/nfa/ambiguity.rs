@@ -0,0 +1,269 @@
+use super::State::*;
+use super::{StateId, NFA};
+
+/*
+ * Analyses of how many different paths through an NFA can match the same
+ * input. A backtracking matcher tries every one of them before giving up on
+ * a line, so a pattern with many such paths can take it exponentially long
+ * to reject an input, or loop forever if it can cycle without consuming any.
+ */
+
+impl NFA {
+    /**
+     * Whether the NFA can go around a cycle of epsilon transitions without
+     * consuming any input, as (a*)* can.
+     */
+    pub fn has_epsilon_cycle(&self) -> bool {
+        // depth first search for a back edge, with an explicit stack; states
+        // are unseen, on the current path, or finished
+        let mut on_path = vec![false; self.states.len()];
+        let mut finished = vec![false; self.states.len()];
+        for root in 0..self.states.len() {
+            if finished[root] {
+                continue;
+            }
+            let mut stack = vec![(root, self.epsilon_targets(root), 0)];
+            on_path[root] = true;
+            while let Some((id, targets, next)) = stack.last_mut() {
+                if let Some(&target) = targets.get(*next) {
+                    *next += 1;
+                    if on_path[target] {
+                        return true;
+                    }
+                    if !finished[target] {
+                        on_path[target] = true;
+                        stack.push((target, self.epsilon_targets(target), 0));
+                    }
+                } else {
+                    on_path[*id] = false;
+                    finished[*id] = true;
+                    stack.pop();
+                }
+            }
+        }
+        false
+    }
+
+    /**
+     * Whether some input can be matched along exponentially many paths, as
+     * with (a|a)* or (a+)+. That happens exactly when some state can return
+     * to itself along two different paths that consume the same chars, or
+     * can do so without consuming any at all.
+     */
+    pub fn is_exponentially_ambiguous(&self) -> bool {
+        if self.has_epsilon_cycle() {
+            return true;
+        }
+
+        // Collapse epsilon transitions: each Match state leads to the Match
+        // states reachable from where it goes, counting (up to two) how many
+        // different epsilon paths lead there.
+        let paths = self.epsilon_paths();
+        let edges: Vec<&[(StateId, u8)]> = (0..self.states.len())
+            .map(|id| match &self.states[id] {
+                Match(_, Some(next)) => paths[*next].as_slice(),
+                _ => &[],
+            })
+            .collect();
+
+        // Two epsilon paths between the same states on a cycle give two
+        // different ways around it.
+        let component = components(self.states.len(), |id| {
+            edges[id].iter().map(|(to, _)| *to).collect()
+        });
+        for (from, targets) in edges.iter().enumerate() {
+            for (to, count) in targets.iter() {
+                if *count > 1 && component[from] == component[*to] {
+                    return true;
+                }
+            }
+        }
+
+        // Otherwise, run two copies of the NFA side by side over the same
+        // input. If the pair can split apart from a state and come back
+        // together there, the state has two different cycles with one label.
+        let n = self.states.len();
+        let label = |id: StateId| match &self.states[id] {
+            Match(c, _) => Some(c),
+            _ => None,
+        };
+        let pair_component = components(n * n, |pair| {
+            let (p, q) = (pair / n, pair % n);
+            let mut next = vec![];
+            for (p2, _) in edges[p].iter() {
+                for (q2, _) in edges[q].iter() {
+                    if let (Some(lhs), Some(rhs)) = (label(*p2), label(*q2)) {
+                        if lhs.overlaps(rhs) {
+                            next.push(p2 * n + q2);
+                        }
+                    }
+                }
+            }
+            next
+        });
+        let mut diagonal = vec![false; n * n];
+        for id in 0..n {
+            diagonal[pair_component[id * n + id]] = true;
+        }
+        (0..n * n).any(|pair| {
+            let (p, q) = (pair / n, pair % n);
+            p != q && !edges[p].is_empty() && diagonal[pair_component[pair]]
+        })
+    }
+
+    /**
+     * The states an epsilon transition can lead to straight from a state.
+     */
+    fn epsilon_targets(&self, id: StateId) -> Vec<StateId> {
+        match &self.states[id] {
+            Start(next) | Epsilon(next) => next.iter().cloned().collect(),
+            Split(lhs, rhs) => lhs.iter().chain(rhs.iter()).cloned().collect(),
+            Match(_, _) | End => vec![],
+        }
+    }
+
+    /**
+     * For every state, the Match states reachable from it by epsilon
+     * transitions alone, each with the number of such paths capped at two.
+     * Assumes there are no epsilon cycles.
+     */
+    fn epsilon_paths(&self) -> Vec<Vec<(StateId, u8)>> {
+        let mut paths: Vec<Option<Vec<(StateId, u8)>>> = vec![None; self.states.len()];
+        for root in 0..self.states.len() {
+            let mut stack = vec![root];
+            while let Some(&id) = stack.last() {
+                if paths[id].is_some() {
+                    stack.pop();
+                    continue;
+                }
+                let targets = self.epsilon_targets(id);
+                let pending: Vec<StateId> = targets
+                    .iter()
+                    .cloned()
+                    .filter(|target| paths[*target].is_none())
+                    .collect();
+                if !pending.is_empty() {
+                    stack.extend(pending);
+                    continue;
+                }
+                let mut reached: Vec<(StateId, u8)> = match self.states[id] {
+                    Match(_, _) => vec![(id, 1)],
+                    _ => vec![],
+                };
+                for target in targets {
+                    for (to, count) in paths[target].as_ref().unwrap() {
+                        match reached.iter_mut().find(|(id, _)| id == to) {
+                            Some((_, total)) => *total = (*total + count).min(2),
+                            None => reached.push((*to, *count)),
+                        }
+                    }
+                }
+                paths[id] = Some(reached);
+                stack.pop();
+            }
+        }
+        paths.into_iter().map(Option::unwrap).collect()
+    }
+}
+
+/**
+ * Tarjan's strongly connected components over nodes 0..count, without
+ * recursion. Returns the component each node belongs to. Components are
+ * numbered in reverse topological order: every edge leaving a component
+ * leads to one with a lower number.
+ */
+pub(super) fn components<F: Fn(usize) -> Vec<usize>>(count: usize, successors: F) -> Vec<usize> {
+    let mut index: Vec<Option<usize>> = vec![None; count];
+    let mut low = vec![0; count];
+    let mut on_stack = vec![false; count];
+    let mut stack = vec![];
+    let mut component = vec![0; count];
+    let mut next_index = 0;
+    let mut next_component = 0;
+
+    for root in 0..count {
+        if index[root].is_some() {
+            continue;
+        }
+        let mut calls = vec![(root, successors(root), 0)];
+        index[root] = Some(next_index);
+        low[root] = next_index;
+        next_index += 1;
+        stack.push(root);
+        on_stack[root] = true;
+
+        while let Some((node, next, at)) = calls.last_mut() {
+            let node = *node;
+            if let Some(&to) = next.get(*at) {
+                *at += 1;
+                match index[to] {
+                    None => {
+                        index[to] = Some(next_index);
+                        low[to] = next_index;
+                        next_index += 1;
+                        stack.push(to);
+                        on_stack[to] = true;
+                        calls.push((to, successors(to), 0));
+                    }
+                    Some(to_index) if on_stack[to] => low[node] = low[node].min(to_index),
+                    Some(_) => {}
+                }
+                continue;
+            }
+
+            calls.pop();
+            if Some(low[node]) == index[node] {
+                while let Some(member) = stack.pop() {
+                    on_stack[member] = false;
+                    component[member] = next_component;
+                    if member == node {
+                        break;
+                    }
+                }
+                next_component += 1;
+            }
+            if let Some((parent, _, _)) = calls.last() {
+                low[*parent] = low[*parent].min(low[node]);
+            }
+        }
+    }
+    component
+}
+
+#[cfg(test)]
+mod ambiguity_tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::tokenizer::Tokenizer;
+
+    // builds the NFA without simplifying, so the pattern is analyzed as written
+    fn nfa(pattern: &str) -> NFA {
+        NFA::from_ast(&Parser::parse(Tokenizer::new(pattern)).unwrap())
+    }
+
+    #[test]
+    fn epsilon_cycles() {
+        assert!(nfa("(a*)*").has_epsilon_cycle());
+        assert!(nfa("(b|a*)+").has_epsilon_cycle());
+        assert!(!nfa("a*b*").has_epsilon_cycle());
+        assert!(!nfa("(ab*)*").has_epsilon_cycle());
+    }
+
+    #[test]
+    fn exponential() {
+        assert!(nfa("(a|a)*").is_exponentially_ambiguous());
+        assert!(nfa("(a+)+").is_exponentially_ambiguous());
+        assert!(nfa("x(.|a)*y").is_exponentially_ambiguous());
+        assert!(nfa("([a-c]|b)*").is_exponentially_ambiguous());
+        assert!(nfa("(aa|a)*").is_exponentially_ambiguous());
+    }
+
+    #[test]
+    fn unambiguous() {
+        assert!(!nfa("(a|b)*").is_exponentially_ambiguous());
+        assert!(!nfa("(ab|a)*").is_exponentially_ambiguous());
+        assert!(!nfa("(ab*c)*").is_exponentially_ambiguous());
+        assert!(!nfa("a*b*").is_exponentially_ambiguous());
+        assert!(!nfa("(a|a)b").is_exponentially_ambiguous());
+    }
+}
@@ -0,0 +1,249 @@
+use super::dfa::representative;
+use super::State::*;
+use super::{Char, StateId, NFA};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/*
+ * Test fixtures that exercise every transition of an NFA, for thegrep
+ * --gen-cover. Random strings from -g can miss a rarely taken branch; these
+ * cannot.
+ */
+
+// a transition out of a state: which of its branches, 0 or 1 for a Split
+type Edge = (StateId, usize);
+
+impl NFA {
+    /**
+     * Strings the NFA matches that, between them, take every Match edge and
+     * both branches of every Split at least once. Each string is built
+     * greedily, heading for the nearest transition not yet taken until none
+     * are in reach, then for the end by the shortest way. Strings whose
+     * transitions all turn out to be taken by others are then dropped, so
+     * no string can be left out. Transitions no matching string can take,
+     * such as those of an empty class, are not counted.
+     */
+    pub fn cover(&self) -> Vec<String> {
+        let useful = self.useful_states();
+        let wanted: HashSet<Edge> = (0..self.states.len())
+            .filter(|id| useful[*id])
+            .flat_map(|id| self.transitions(id))
+            .filter(|(_, to, c)| useful[*to] && !matches!(c, Some(None)))
+            .map(|(edge, _, _)| edge)
+            .collect();
+
+        let mut covered: HashSet<Edge> = HashSet::new();
+        let mut strings: Vec<(String, Vec<Edge>)> = vec![];
+        while wanted.iter().any(|edge| !covered.contains(edge)) {
+            let mut string = String::new();
+            let mut taken = vec![];
+            let mut at = self.start;
+            loop {
+                let (distance, parent) = self.paths_from(at, &useful);
+                let nearest = wanted
+                    .iter()
+                    .filter(|edge| !covered.contains(edge) && distance[edge.0] != usize::MAX)
+                    .min_by_key(|(id, branch)| (distance[*id], *id, *branch))
+                    .cloned();
+                let goal = match nearest {
+                    Some((id, _)) => id,
+                    None => (0..self.states.len())
+                        .filter(|id| matches!(self.states[*id], End) && distance[*id] != usize::MAX)
+                        .min_by_key(|id| distance[*id])
+                        .unwrap(),
+                };
+
+                let mut path = vec![];
+                let mut id = goal;
+                while let Some(edge) = parent.get(&id) {
+                    path.push(*edge);
+                    id = edge.0;
+                }
+                path.reverse();
+                path.extend(nearest);
+                for edge in path {
+                    let (_, to, c) = self.transitions(edge.0)[edge.1];
+                    string.extend(c.flatten());
+                    covered.insert(edge);
+                    taken.push(edge);
+                    at = to;
+                }
+                if nearest.is_none() {
+                    break;
+                }
+            }
+            strings.push((string, taken));
+        }
+
+        // drop strings, latest first, that only take transitions others take too
+        let mut times: HashMap<Edge, usize> = HashMap::new();
+        for (_, taken) in &strings {
+            for edge in taken.iter().collect::<HashSet<_>>() {
+                *times.entry(*edge).or_insert(0) += 1;
+            }
+        }
+        let mut kept = vec![true; strings.len()];
+        for i in (0..strings.len()).rev() {
+            let edges: HashSet<&Edge> = strings[i].1.iter().collect();
+            if edges.iter().all(|edge| !wanted.contains(edge) || times[edge] > 1) {
+                kept[i] = false;
+                for edge in edges {
+                    *times.get_mut(edge).unwrap() -= 1;
+                }
+            }
+        }
+        strings
+            .into_iter()
+            .zip(kept)
+            .filter(|(_, kept)| *kept)
+            .map(|((string, _), _)| string)
+            .collect()
+    }
+
+    /**
+     * The transitions out of a state, each with where it leads and, for a
+     * Match, the char to take it on: None for an empty class.
+     */
+    fn transitions(&self, id: StateId) -> Vec<(Edge, StateId, Option<Option<char>>)> {
+        match &self.states[id] {
+            Start(Some(next)) | Epsilon(Some(next)) => vec![((id, 0), *next, None)],
+            Split(lhs, rhs) => lhs
+                .iter()
+                .chain(rhs.iter())
+                .enumerate()
+                .map(|(branch, to)| ((id, branch), *to, None))
+                .collect(),
+            Match(c, Some(next)) => vec![((id, 0), *next, Some(example(c)))],
+            _ => vec![],
+        }
+    }
+
+    /**
+     * How many chars it takes to get from one state to each of the others
+     * through useful states, and the transition that gets to each first.
+     */
+    fn paths_from(&self, from: StateId, useful: &[bool]) -> (Vec<usize>, HashMap<StateId, Edge>) {
+        let mut distance = vec![usize::MAX; self.states.len()];
+        let mut parent = HashMap::new();
+        let mut queue = VecDeque::new();
+        distance[from] = 0;
+        queue.push_back(from);
+        while let Some(id) = queue.pop_front() {
+            for (edge, to, c) in self.transitions(id) {
+                let weight = match c {
+                    Some(None) => continue,
+                    Some(Some(_)) => 1,
+                    None => 0,
+                };
+                if useful[to] && distance[id] + weight < distance[to] {
+                    distance[to] = distance[id] + weight;
+                    parent.insert(to, edge);
+                    if weight == 0 {
+                        queue.push_front(to);
+                    } else {
+                        queue.push_back(to);
+                    }
+                }
+            }
+        }
+        (distance, parent)
+    }
+}
+
+/**
+ * A readable char a Match edge can be taken on, if there is any.
+ */
+fn example(c: &Char) -> Option<char> {
+    match c {
+        Char::Literal(c) => Some(*c),
+        Char::Any => Some(representative(('\0', std::char::MAX))),
+        Char::Set(ranges) => ranges
+            .iter()
+            .map(|range| representative(*range))
+            .find(|c| c.is_ascii_graphic())
+            .or_else(|| ranges.first().map(|(start, _)| *start)),
+    }
+}
+
+#[cfg(test)]
+mod cover_tests {
+    use super::*;
+
+    fn cover(pattern: &str) -> Vec<String> {
+        let nfa = NFA::from(pattern).unwrap();
+        let strings = nfa.cover();
+        let dfa = nfa.to_dfa();
+        for string in &strings {
+            assert!(dfa.accepts(string), "{:?} does not match {}", string, pattern);
+        }
+        strings
+    }
+
+    #[test]
+    fn alternations() {
+        assert_eq!(cover("abc"), vec!["abc"]);
+        assert_eq!(cover("cat|dog|cow"), vec!["cat", "dog", "cow"]);
+        assert_eq!(cover("(ab|c)(d|ef)").len(), 2);
+    }
+
+    #[test]
+    fn loops() {
+        // once around the loop takes both branches of its split
+        assert_eq!(cover("xa*"), vec!["xa"]);
+        assert_eq!(cover("(ab|cd)+"), vec!["abcd"]);
+    }
+
+    #[test]
+    fn transitions_that_cannot_be_taken() {
+        assert_eq!(cover("a|[]b"), vec!["a"]);
+        assert!(cover("a[]").is_empty());
+    }
+
+    #[test]
+    fn every_transition_is_taken() {
+        let nfa = NFA::from("(ab|c)*d?e|[x-z]+").unwrap();
+        let strings = nfa.cover();
+        let useful = nfa.useful_states();
+        for id in 0..nfa.states.len() {
+            if !useful[id] {
+                continue;
+            }
+            for (edge, to, _) in nfa.transitions(id) {
+                if !useful[to] {
+                    continue;
+                }
+                let taken = strings.iter().any(|string| nfa.takes(string, edge));
+                assert!(taken, "no string takes {:?} in {:?}", edge, strings);
+            }
+        }
+    }
+
+    impl NFA {
+        // whether some accepting path for the whole string goes along the edge
+        fn takes(&self, string: &str, edge: Edge) -> bool {
+            let chars: Vec<char> = string.chars().collect();
+            let mut seen = HashSet::new();
+            let mut stack = vec![(self.start, 0, false)];
+            while let Some((id, at, used)) = stack.pop() {
+                if !seen.insert((id, at, used)) {
+                    continue;
+                }
+                if let End = self.states[id] {
+                    if at == chars.len() && used {
+                        return true;
+                    }
+                }
+                for (taken, to, c) in self.transitions(id) {
+                    let used = used || taken == edge;
+                    match (c, &self.states[id]) {
+                        (None, _) => stack.push((to, at, used)),
+                        (Some(_), Match(c, _)) if at < chars.len() && c.matches(chars[at]) => {
+                            stack.push((to, at + 1, used))
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            false
+        }
+    }
+}
@@ -0,0 +1,1080 @@
+use super::super::parser::{build_anychar, build_char, build_class, normalize_ranges, AST};
+use super::eliminate::eliminate;
+use super::State::*;
+use super::{Char, Fragment, StateId, NFA};
+use rand::Rng;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+/*
+ * Deterministic automata built from NFAs by the subset construction, for
+ * questions about the whole language of a pattern: the set of strings it
+ * matches from start to end, rather than somewhere within a line.
+ */
+
+/**
+ * A complete DFA over an alphabet of disjoint char ranges. Every state has a
+ * transition on every range, so strings the pattern can never match end up
+ * in a dead state rather than falling off the automaton.
+ */
+#[derive(Debug)]
+pub struct DFA {
+    alphabet: Vec<(char, char)>,
+    accepting: Vec<bool>,
+    // next[state][symbol], where symbols index the alphabet
+    next: Vec<Vec<usize>>,
+}
+
+/**
+ * The chars `.` stands for when strings are counted or sampled, unless
+ * another alphabet is given.
+ */
+pub const ALPHANUMERIC: &[(char, char)] = &[('0', '9'), ('A', 'Z'), ('a', 'z')];
+
+// a state of each of two DFAs run side by side
+type Pair = (usize, usize);
+
+/**
+ * The answer to a yes or no question about the strings patterns match,
+ * with a string that shows why when the answer is no.
+ */
+#[derive(Debug, PartialEq)]
+pub enum Verdict {
+    Yes,
+    No(String),
+}
+
+impl Verdict {
+    pub fn holds(&self) -> bool {
+        *self == Verdict::Yes
+    }
+
+    fn from_witness(witness: Option<String>) -> Verdict {
+        match witness {
+            Some(witness) => Verdict::No(witness),
+            None => Verdict::Yes,
+        }
+    }
+}
+
+/**
+ * Whether every string `a` matches is also matched by `b`. If not, the
+ * witness is a shortest string matched by `a` alone.
+ */
+pub fn is_subset(a: &NFA, b: &NFA) -> Verdict {
+    let alphabet = alphabet(&[a, b]);
+    let lhs = DFA::new(a, &alphabet);
+    let rhs = DFA::new(b, &alphabet);
+    Verdict::from_witness(lhs.product_witness(&rhs, |lhs, rhs| lhs && !rhs))
+}
+
+/**
+ * Whether the NFA matches no strings at all. If it matches some, the
+ * witness is a shortest one.
+ */
+pub fn is_empty(nfa: &NFA) -> Verdict {
+    let dfa = nfa.to_dfa();
+    // paired with itself, the product only ever visits states (q, q)
+    Verdict::from_witness(dfa.product_witness(&dfa, |accepts, _| accepts))
+}
+
+/**
+ * Whether the NFA matches every string. If not, the witness is a shortest
+ * string it does not match.
+ */
+pub fn is_universal(nfa: &NFA) -> Verdict {
+    let dfa = nfa.to_dfa();
+    Verdict::from_witness(dfa.product_witness(&dfa, |accepts, _| !accepts))
+}
+
+impl NFA {
+    /**
+     * Determinize the NFA over the smallest alphabet that tells its edges
+     * apart.
+     */
+    pub fn to_dfa(&self) -> DFA {
+        DFA::new(self, &alphabet(&[self]))
+    }
+
+    /**
+     * A pattern matching the same strings as the NFA, written without &, ~
+     * or -. See DFA::to_ast.
+     */
+    pub fn to_ast(&self) -> AST {
+        self.to_dfa().to_ast()
+    }
+
+    /**
+     * An NFA matching exactly the strings this one does not, as if the
+     * whole pattern were wrapped in ~( ).
+     */
+    pub fn complement(&self) -> NFA {
+        NFA::from_dfa(&self.to_dfa().complement(), self.named.clone())
+    }
+
+    /**
+     * An NFA matching the strings both NFAs match, from the product of
+     * their DFAs.
+     */
+    pub fn intersection(&self, other: &NFA) -> NFA {
+        let alphabet = alphabet(&[self, other]);
+        let dfa = DFA::new(self, &alphabet).product(&DFA::new(other, &alphabet), |lhs, rhs| lhs && rhs);
+        NFA::from_dfa(&dfa, [self.named.clone(), other.named.clone()].concat())
+    }
+
+    /**
+     * An NFA of its own for a DFA, given the chars named by the patterns it
+     * came from.
+     */
+    fn from_dfa(dfa: &DFA, named: Vec<(char, char)>) -> NFA {
+        let mut nfa = NFA::new();
+        nfa.start = nfa.add_state(Start(None));
+        let body = nfa.embed(dfa);
+        nfa.join(nfa.start, body.start);
+        let end = nfa.add_state(End);
+        nfa.join_fragment(&body, end);
+        nfa.shortest = nfa.min_length();
+        nfa.named = named;
+        nfa
+    }
+
+    /**
+     * A shortest string the NFA matches, or None if it matches nothing. It
+     * is the first string NFA::enumerate gives, so its chars are readable
+     * where the pattern allows.
+     */
+    pub fn shortest_match_string(&self) -> Option<String> {
+        let dfa = self.to_dfa();
+        dfa.product_witness(&dfa, |accepts, _| accepts)
+    }
+
+    /**
+     * Every string the NFA matches that is at most max_len chars long,
+     * shorter strings first. Strings of the same length are in order of the
+     * sets of chars the NFA tells apart, and within each set readable chars
+     * come first, in the order representative picks them, so . gives "a"
+     * before "\0". The strings are found as they are asked for, since
+     * patterns like .* match more than could ever be kept in memory.
+     */
+    pub fn enumerate(&self, max_len: usize) -> Strings {
+        let dfa = self.to_dfa();
+        // reach[k][q]: some string of exactly k chars leads from q to acceptance
+        let mut reach = vec![dfa.accepting.clone()];
+        for k in 1..=max_len {
+            let row = dfa
+                .next
+                .iter()
+                .map(|targets| targets.iter().any(|to| reach[k - 1][*to]))
+                .collect();
+            reach.push(row);
+        }
+        Strings {
+            dfa,
+            reach,
+            max_len,
+            length: 0,
+            started: false,
+            path: vec![],
+        }
+    }
+
+    /**
+     * How many strings of exactly len chars the NFA matches, with `.` only
+     * standing for alphanumerics.
+     */
+    pub fn count_strings(&self, len: usize) -> u128 {
+        self.sampler(len, ALPHANUMERIC).count()
+    }
+
+    /**
+     * Count the strings of exactly len chars the NFA matches, so they can be
+     * sampled uniformly. Chars named in the pattern, alone or in a class,
+     * stand for themselves; `.`, and anything else the pattern leaves open,
+     * only stands for chars of the given alphabet.
+     */
+    pub fn sampler(&self, len: usize, alphabet: &[(char, char)]) -> Sampler {
+        let dfa = self.to_dfa();
+        let alphabet = normalize_ranges(alphabet.to_vec());
+
+        // the alphabet cuts chars wherever a name starts or ends, so each
+        // symbol is either all named or not named at all
+        let chars: Vec<Vec<(char, char)>> = dfa
+            .alphabet
+            .iter()
+            .map(|&(start, end)| {
+                if self.named.iter().any(|(s, e)| *s <= start && start <= *e) {
+                    vec![(start, end)]
+                } else {
+                    alphabet
+                        .iter()
+                        .filter(|(s, e)| *s <= end && start <= *e)
+                        .map(|(s, e)| (start.max(*s), end.min(*e)))
+                        .collect()
+                }
+            })
+            .collect();
+        let weights: Vec<u128> = chars.iter().map(|ranges| size(ranges)).collect();
+
+        let mut counts: Vec<Vec<u128>> =
+            vec![dfa.accepting.iter().map(|accepting| *accepting as u128).collect()];
+        for k in 1..=len {
+            let row: Vec<u128> = dfa
+                .next
+                .iter()
+                .map(|targets| {
+                    targets.iter().zip(&weights).fold(0u128, |total, (to, weight)| {
+                        total.saturating_add(weight.saturating_mul(counts[k - 1][*to]))
+                    })
+                })
+                .collect();
+            counts.push(row);
+        }
+        Sampler { dfa, chars, counts }
+    }
+
+    /**
+     * A shortest string matched by exactly one of the two NFAs, or None if
+     * they match exactly the same strings.
+     */
+    pub fn distinguish(&self, other: &NFA) -> Option<String> {
+        let alphabet = alphabet(&[self, other]);
+        let lhs = DFA::new(self, &alphabet);
+        let rhs = DFA::new(other, &alphabet);
+        lhs.product_witness(&rhs, |lhs, rhs| lhs != rhs)
+    }
+
+    /**
+     * The states reachable from the given ones by epsilon transitions, the
+     * given ones included, sorted.
+     */
+    fn epsilon_closure(&self, from: &[StateId]) -> Vec<StateId> {
+        let mut seen = vec![false; self.states.len()];
+        let mut stack = from.to_vec();
+        let mut closure = vec![];
+        while let Some(id) = stack.pop() {
+            if seen[id] {
+                continue;
+            }
+            seen[id] = true;
+            closure.push(id);
+            match &self.states[id] {
+                Start(next) | Epsilon(next) => stack.extend(next),
+                Split(lhs, rhs) => stack.extend(lhs.iter().chain(rhs.iter())),
+                Match(_, _) | End => {}
+            }
+        }
+        closure.sort();
+        closure
+    }
+
+    /**
+     * The fragment for an intersection, difference or complement, whose
+     * operands have already been built as fragments at the end of the arena.
+     * They are taken back out as NFAs of their own, combined as DFAs, and
+     * the resulting DFA is put in their place.
+     */
+    pub(super) fn gen_dfa_fragment(&mut self, node: &AST, mut children: Vec<Fragment>) -> Fragment {
+        let dfa = match node {
+            AST::Complement(_) => self.detach(&children.pop().unwrap()).to_dfa().complement(),
+            _ => {
+                // the rhs was built after the lhs, so it is taken out first
+                let rhs = self.detach(&children.pop().unwrap());
+                let lhs = self.detach(&children.pop().unwrap());
+                let alphabet = alphabet(&[&lhs, &rhs]);
+                let (lhs, rhs) = (DFA::new(&lhs, &alphabet), DFA::new(&rhs, &alphabet));
+                match node {
+                    AST::Intersection(_, _) => lhs.product(&rhs, |lhs, rhs| lhs && rhs),
+                    _ => lhs.product(&rhs, |lhs, rhs| lhs && !rhs),
+                }
+            }
+        };
+        self.embed(&dfa)
+    }
+
+    /**
+     * Move the most recently built fragment out of the arena and into an
+     * NFA of its own.
+     */
+    fn detach(&mut self, fragment: &Fragment) -> NFA {
+        let shift = |id: StateId| id - fragment.first + 1;
+        let mut nfa = NFA::new();
+        nfa.add_state(Start(Some(shift(fragment.start))));
+        for state in self.states.drain(fragment.first..) {
+            nfa.add_state(match state {
+                Start(next) => Start(next.map(shift)),
+                Match(c, next) => Match(c, next.map(shift)),
+                Split(lhs, rhs) => Split(lhs.map(shift), rhs.map(shift)),
+                Epsilon(next) => Epsilon(next.map(shift)),
+                End => End,
+            });
+        }
+        let end = nfa.add_state(End);
+        for loose in &fragment.ends {
+            nfa.join(shift(*loose), end);
+        }
+        nfa
+    }
+
+    /**
+     * Add a DFA's states to the arena as a fragment. States that can never
+     * reach an accepting one are left out, so every path through the
+     * fragment can still be finished.
+     */
+    fn embed(&mut self, dfa: &DFA) -> Fragment {
+        let live = dfa.live();
+        if !live[0] {
+            return self.gen_match(Char::Set(vec![]));
+        }
+
+        let first = self.states.len();
+        let mut entries = vec![0; dfa.next.len()];
+        let mut edges: Vec<(StateId, usize)> = vec![];
+        let mut ends = vec![];
+        for (from, row) in dfa.next.iter().enumerate() {
+            if !live[from] {
+                continue;
+            }
+            // one Match state per target, labelled with every range leading there
+            let mut targets: Vec<(usize, Vec<(char, char)>)> = vec![];
+            for (symbol, to) in row.iter().enumerate() {
+                if !live[*to] {
+                    continue;
+                }
+                match targets.iter_mut().find(|(target, _)| target == to) {
+                    Some((_, ranges)) => ranges.push(dfa.alphabet[symbol]),
+                    None => targets.push((*to, vec![dfa.alphabet[symbol]])),
+                }
+            }
+            let mut options = vec![];
+            for (to, ranges) in targets {
+                let id = self.add_state(Match(label(ranges), None));
+                edges.push((id, to));
+                options.push(id);
+            }
+            if dfa.accepting[from] {
+                let exit = self.add_state(Epsilon(None));
+                ends.push(exit);
+                options.push(exit);
+            }
+
+            let mut entry = options.pop().unwrap();
+            while let Some(option) = options.pop() {
+                entry = self.add_state(Split(Some(option), Some(entry)));
+            }
+            entries[from] = entry;
+        }
+        for (id, to) in edges {
+            self.join(id, entries[to]);
+        }
+
+        Fragment {
+            start: entries[0],
+            ends,
+            first,
+        }
+    }
+}
+
+impl DFA {
+    /**
+     * The subset construction over the given alphabet, which must split
+     * the chars wherever an edge label of the NFA does.
+     */
+    fn new(nfa: &NFA, alphabet: &[(char, char)]) -> DFA {
+        let mut dfa = DFA {
+            alphabet: alphabet.to_vec(),
+            accepting: vec![],
+            next: vec![],
+        };
+        let mut ids: HashMap<Vec<StateId>, usize> = HashMap::new();
+        let mut subsets: Vec<Vec<StateId>> = vec![];
+
+        let start = nfa.epsilon_closure(&[nfa.start]);
+        ids.insert(start.clone(), 0);
+        subsets.push(start);
+
+        let mut at = 0;
+        while at < subsets.len() {
+            let subset = subsets[at].clone();
+            dfa.accepting.push(subset.iter().any(|id| matches!(nfa.states[*id], End)));
+            let mut row = Vec::with_capacity(alphabet.len());
+            for (symbol, _) in alphabet {
+                let moved: Vec<StateId> = subset
+                    .iter()
+                    .filter_map(|id| match &nfa.states[*id] {
+                        Match(c, Some(next)) if c.matches(*symbol) => Some(*next),
+                        _ => None,
+                    })
+                    .collect();
+                let target = nfa.epsilon_closure(&moved);
+                let id = match ids.get(&target) {
+                    Some(id) => *id,
+                    None => {
+                        ids.insert(target.clone(), subsets.len());
+                        subsets.push(target);
+                        subsets.len() - 1
+                    }
+                };
+                row.push(id);
+            }
+            dfa.next.push(row);
+            at += 1;
+        }
+        dfa
+    }
+
+    /**
+     * The DFA matching exactly the strings this one does not. Since the DFA
+     * is complete, that is only a matter of swapping which states accept.
+     */
+    fn complement(mut self) -> DFA {
+        for accepting in self.accepting.iter_mut() {
+            *accepting = !*accepting;
+        }
+        self
+    }
+
+    /**
+     * Run two DFAs over the same alphabet side by side, as one DFA whose
+     * states are pairs of theirs, accepting where `accept` says it should.
+     */
+    fn product<F: Fn(bool, bool) -> bool>(&self, other: &DFA, accept: F) -> DFA {
+        assert_eq!(self.alphabet, other.alphabet);
+        let mut dfa = DFA {
+            alphabet: self.alphabet.clone(),
+            accepting: vec![],
+            next: vec![],
+        };
+        let mut ids: HashMap<Pair, usize> = HashMap::new();
+        let mut pairs: Vec<Pair> = vec![(0, 0)];
+        ids.insert((0, 0), 0);
+
+        let mut at = 0;
+        while at < pairs.len() {
+            let (lhs, rhs) = pairs[at];
+            dfa.accepting.push(accept(self.accepting[lhs], other.accepting[rhs]));
+            let mut row = Vec::with_capacity(self.alphabet.len());
+            for symbol in 0..self.alphabet.len() {
+                let next = (self.next[lhs][symbol], other.next[rhs][symbol]);
+                let id = *ids.entry(next).or_insert_with(|| {
+                    pairs.push(next);
+                    pairs.len() - 1
+                });
+                row.push(id);
+            }
+            dfa.next.push(row);
+            at += 1;
+        }
+        dfa
+    }
+
+    /**
+     * Which states can still reach an accepting state.
+     */
+    fn live(&self) -> Vec<bool> {
+        let mut from: Vec<Vec<usize>> = vec![vec![]; self.next.len()];
+        for (state, row) in self.next.iter().enumerate() {
+            for to in row {
+                from[*to].push(state);
+            }
+        }
+        let mut live = self.accepting.clone();
+        let mut stack: Vec<usize> = (0..live.len()).filter(|state| live[*state]).collect();
+        while let Some(state) = stack.pop() {
+            for previous in &from[state] {
+                if !live[*previous] {
+                    live[*previous] = true;
+                    stack.push(*previous);
+                }
+            }
+        }
+        live
+    }
+
+    /**
+     * The DFA with the fewest states that matches the same strings. States
+     * start out split into accepting and not, and classes are split again
+     * until the states in each agree on which class every symbol leads to.
+     * The states are numbered breadth first from the start.
+     */
+    pub fn minimize(&self) -> DFA {
+        let mut class: Vec<usize> = self.accepting.iter().map(|accepting| *accepting as usize).collect();
+        let mut count = 0;
+        loop {
+            let mut signatures: HashMap<(usize, Vec<usize>), usize> = HashMap::new();
+            let refined: Vec<usize> = (0..self.next.len())
+                .map(|state| {
+                    let signature = (class[state], self.next[state].iter().map(|to| class[*to]).collect());
+                    let fresh = signatures.len();
+                    *signatures.entry(signature).or_insert(fresh)
+                })
+                .collect();
+            class = refined;
+            if signatures.len() == count {
+                break;
+            }
+            count = signatures.len();
+        }
+
+        let mut number: Vec<Option<usize>> = vec![None; count];
+        let mut order = vec![];
+        let mut queue = VecDeque::new();
+        number[class[0]] = Some(0);
+        queue.push_back(0);
+        while let Some(state) = queue.pop_front() {
+            order.push(state);
+            for to in &self.next[state] {
+                if number[class[*to]].is_none() {
+                    number[class[*to]] = Some(order.len() + queue.len());
+                    queue.push_back(*to);
+                }
+            }
+        }
+        DFA {
+            alphabet: self.alphabet.clone(),
+            accepting: order.iter().map(|state| self.accepting[*state]).collect(),
+            next: order
+                .iter()
+                .map(|state| self.next[*state].iter().map(|to| number[class[*to]].unwrap()).collect())
+                .collect(),
+        }
+    }
+
+    /**
+     * A pattern matching the same strings as the DFA, found by state
+     * elimination on its minimal form, so it only needs the operators of
+     * plain patterns. This turns the results of &, ~ and - back into
+     * something readable.
+     */
+    pub fn to_ast(&self) -> AST {
+        let dfa = self.minimize();
+        let live = dfa.live();
+        let mut edges = vec![];
+        for (from, row) in dfa.next.iter().enumerate() {
+            if !live[from] {
+                continue;
+            }
+            let mut targets: BTreeMap<usize, Vec<(char, char)>> = BTreeMap::new();
+            for (symbol, to) in row.iter().enumerate() {
+                if live[*to] {
+                    targets.entry(*to).or_default().push(dfa.alphabet[symbol]);
+                }
+            }
+            for (to, ranges) in targets {
+                let ast = match label(ranges) {
+                    Char::Literal(c) => build_char(c),
+                    Char::Any => build_anychar(),
+                    Char::Set(ranges) => build_class(ranges),
+                };
+                edges.push((from, to, ast));
+            }
+        }
+        eliminate(dfa.next.len(), 0, &dfa.accepting, edges)
+    }
+
+    /**
+     * Whether the DFA matches the whole of the input.
+     */
+    pub fn accepts(&self, input: &str) -> bool {
+        let mut state = 0;
+        for c in input.chars() {
+            state = self.step(state, c);
+        }
+        self.is_accepting(state)
+    }
+
+    /**
+     * The state reading c leads to from a state, where 0 is the start.
+     */
+    pub fn step(&self, state: usize, c: char) -> usize {
+        self.next[state][self.symbol(c)]
+    }
+
+    pub fn is_accepting(&self, state: usize) -> bool {
+        self.accepting[state]
+    }
+
+    /**
+     * The index of the alphabet range holding a char.
+     */
+    fn symbol(&self, c: char) -> usize {
+        self.alphabet
+            .binary_search_by(|(start, end)| {
+                if *end < c {
+                    std::cmp::Ordering::Less
+                } else if c < *start {
+                    std::cmp::Ordering::Greater
+                } else {
+                    std::cmp::Ordering::Equal
+                }
+            })
+            .unwrap()
+    }
+
+    /**
+     * Run two DFAs over the same alphabet side by side, breadth first, and
+     * return a shortest string that leaves them in states whose acceptance
+     * satisfies `wanted`, or None if no string does.
+     */
+    fn product_witness<F: Fn(bool, bool) -> bool>(&self, other: &DFA, wanted: F) -> Option<String> {
+        assert_eq!(self.alphabet, other.alphabet);
+        // each pair reached, with the pair and symbol it was first reached from
+        let mut parent: HashMap<Pair, Option<(Pair, usize)>> = HashMap::new();
+        let mut queue = VecDeque::new();
+        parent.insert((0, 0), None);
+        queue.push_back((0, 0));
+
+        while let Some(pair) = queue.pop_front() {
+            if wanted(self.accepting[pair.0], other.accepting[pair.1]) {
+                let mut witness = vec![];
+                let mut at = pair;
+                while let Some((from, symbol)) = parent[&at] {
+                    witness.push(representative(self.alphabet[symbol]));
+                    at = from;
+                }
+                return Some(witness.into_iter().rev().collect());
+            }
+            for symbol in 0..self.alphabet.len() {
+                let next = (self.next[pair.0][symbol], other.next[pair.1][symbol]);
+                if let Entry::Vacant(entry) = parent.entry(next) {
+                    entry.insert(Some((pair, symbol)));
+                    queue.push_back(next);
+                }
+            }
+        }
+        None
+    }
+}
+
+/**
+ * The number of strings of one length an NFA matches, from NFA::sampler.
+ * Counts past u128::MAX stop there, and sampling is then no longer quite
+ * uniform.
+ */
+pub struct Sampler {
+    dfa: DFA,
+    // the chars each symbol of the DFA's alphabet stands for
+    chars: Vec<Vec<(char, char)>>,
+    // counts[k][q]: the number of strings of k chars leading from q to acceptance
+    counts: Vec<Vec<u128>>,
+}
+
+impl Sampler {
+    pub fn count(&self) -> u128 {
+        self.counts[self.counts.len() - 1][0]
+    }
+
+    /**
+     * Pick one of the strings, each as likely as any other, or None if
+     * there are none. At each step a symbol is chosen in proportion to how
+     * many strings go through it.
+     */
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> Option<String> {
+        if self.count() == 0 {
+            return None;
+        }
+        let mut state = 0;
+        let mut string = String::new();
+        for left in (0..self.counts.len() - 1).rev() {
+            let total = self.counts[left + 1][state];
+            let mut pick = rng.gen_range(0, total);
+            for (symbol, ranges) in self.chars.iter().enumerate() {
+                let to = self.dfa.next[state][symbol];
+                let through = size(ranges).saturating_mul(self.counts[left][to]);
+                if pick < through {
+                    // every char of the symbol leads on to as many strings
+                    string.push(nth_char(ranges, pick / self.counts[left][to]));
+                    state = to;
+                    break;
+                }
+                pick -= through;
+            }
+        }
+        Some(string)
+    }
+}
+
+/**
+ * The strings of an NFA::enumerate, found by a depth first search through
+ * the DFA for each length in turn.
+ */
+pub struct Strings {
+    dfa: DFA,
+    reach: Vec<Vec<bool>>,
+    max_len: usize,
+    // the length of the strings currently being found
+    length: usize,
+    // whether the first string of that length has been found
+    started: bool,
+    // the state each char of the current string was taken from, along with
+    // the symbol it belongs to and how far into the symbol's chars it is
+    path: Vec<(usize, usize, u32)>,
+}
+
+impl Strings {
+    /**
+     * The state the current string leads to.
+     */
+    fn state(&self) -> usize {
+        match self.path.last() {
+            Some((from, symbol, _)) => self.dfa.next[*from][*symbol],
+            None => 0,
+        }
+    }
+
+    /**
+     * The first symbol from `from` on, out of a state, that still leaves a
+     * way to acceptance in exactly the chars left after it.
+     */
+    fn next_symbol(&self, state: usize, from: usize, depth: usize) -> Option<usize> {
+        let left = self.length - depth - 1;
+        (from..self.dfa.alphabet.len()).find(|symbol| self.reach[left][self.dfa.next[state][*symbol]])
+    }
+
+    /**
+     * Extend the current string to the full length with the smallest chars
+     * that can still lead to acceptance.
+     */
+    fn descend(&mut self) {
+        while self.path.len() < self.length {
+            let state = self.state();
+            let symbol = self.next_symbol(state, 0, self.path.len()).unwrap();
+            self.path.push((state, symbol, 0));
+        }
+    }
+
+    /**
+     * Step the current string to the next one of the same length, leaving a
+     * prefix of it for `descend` to finish, or return false if there is none.
+     */
+    fn advance(&mut self) -> bool {
+        while let Some((state, symbol, n)) = self.path.pop() {
+            let (start, end) = self.dfa.alphabet[symbol];
+            if n < end as u32 - start as u32 {
+                self.path.push((state, symbol, n + 1));
+                return true;
+            }
+            if let Some(symbol) = self.next_symbol(state, symbol + 1, self.path.len()) {
+                self.path.push((state, symbol, 0));
+                return true;
+            }
+        }
+        false
+    }
+}
+
+impl Iterator for Strings {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        while self.length <= self.max_len {
+            let found = if self.started {
+                self.advance()
+            } else {
+                self.started = true;
+                self.reach[self.length][0]
+            };
+            if found {
+                self.descend();
+                let alphabet = &self.dfa.alphabet;
+                return Some(self.path.iter().map(|(_, symbol, n)| readable_nth(alphabet[*symbol], *n)).collect());
+            }
+            self.length += 1;
+            self.started = false;
+        }
+        None
+    }
+}
+
+/**
+ * Split all chars into ranges such that every edge label of every NFA
+ * matches either all of a range or none of it.
+ */
+fn alphabet(nfas: &[&NFA]) -> Vec<(char, char)> {
+    // the surrogates are not chars, so they are always cut out
+    let mut cuts: Vec<u32> = vec![0, 0xD800, 0xE000, std::char::MAX as u32 + 1];
+    for nfa in nfas {
+        for state in &nfa.states {
+            match state {
+                Match(Char::Literal(c), _) => cuts.extend(&[*c as u32, *c as u32 + 1]),
+                Match(Char::Set(ranges), _) => {
+                    for (start, end) in ranges {
+                        cuts.extend(&[*start as u32, *end as u32 + 1]);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    cuts.sort();
+    cuts.dedup();
+    cuts.windows(2)
+        .filter(|pair| pair[0] != 0xD800)
+        .map(|pair| {
+            let start = std::char::from_u32(pair[0]).unwrap();
+            let end = std::char::from_u32(pair[1] - 1).unwrap();
+            (start, end)
+        })
+        .collect()
+}
+
+/**
+ * How many chars there are in some ranges.
+ */
+pub(super) fn size(ranges: &[(char, char)]) -> u128 {
+    ranges
+        .iter()
+        .map(|(start, end)| u128::from(*end as u32 - *start as u32 + 1))
+        .sum()
+}
+
+/**
+ * The char n places into some ranges.
+ */
+pub(super) fn nth_char(ranges: &[(char, char)], mut n: u128) -> char {
+    for (start, end) in ranges {
+        let size = u128::from(*end as u32 - *start as u32 + 1);
+        if n < size {
+            return std::char::from_u32(*start as u32 + n as u32).unwrap();
+        }
+        n -= size;
+    }
+    unreachable!()
+}
+
+/**
+ * The edge label matching any of the given alphabet ranges.
+ */
+fn label(ranges: Vec<(char, char)>) -> Char {
+    let ranges = normalize_ranges(ranges);
+    match ranges.as_slice() {
+        [(start, end)] if start == end => Char::Literal(*start),
+        [('\0', '\u{D7FF}'), ('\u{E000}', std::char::MAX)] => Char::Any,
+        _ => Char::Set(ranges),
+    }
+}
+
+/**
+ * A char from a range to show in a witness, readable where possible.
+ */
+pub(super) fn representative(range: (char, char)) -> char {
+    readable_nth(range, 0)
+}
+
+/**
+ * The char n places into a range, with the printable ASCII chars in it
+ * first: lowercase letters, digits, uppercase letters and then the rest.
+ * The other chars follow in order.
+ */
+fn readable_nth((start, end): (char, char), n: u32) -> char {
+    let readable: Vec<char> = "abcdefghijklmnopqrstuvwxyz0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ"
+        .chars()
+        .chain((' '..='~').filter(|c| !c.is_alphanumeric()))
+        .filter(|c| start <= *c && *c <= end)
+        .collect();
+    if let Some(c) = readable.get(n as usize) {
+        return *c;
+    }
+    // the chars left are those below the space and above the tilde
+    let n = n - readable.len() as u32;
+    let below = (start as u32..(end as u32 + 1).min(' ' as u32)).len() as u32;
+    let c = if n < below {
+        start as u32 + n
+    } else {
+        (start as u32).max('~' as u32 + 1) + n - below
+    };
+    std::char::from_u32(c).unwrap()
+}
+
+#[cfg(test)]
+mod dfa_tests {
+    use super::*;
+    use crate::tokenizer::Tokenizer;
+
+    fn nfa(pattern: &str) -> NFA {
+        NFA::from(pattern).unwrap()
+    }
+
+    #[test]
+    fn whole_language() {
+        let dfa = nfa("ab*c|.x").to_dfa();
+        assert!(dfa.accepts("ac"));
+        assert!(dfa.accepts("abbbc"));
+        assert!(dfa.accepts("éx"));
+        assert!(!dfa.accepts("abcd"));
+        assert!(!dfa.accepts("xac"));
+        assert!(!dfa.accepts(""));
+    }
+
+    #[test]
+    fn classes() {
+        let dfa = nfa("[a-f0-9]+").to_dfa();
+        assert!(dfa.accepts("c0ffee"));
+        assert!(!dfa.accepts("coffee"));
+    }
+
+    #[test]
+    fn equivalent() {
+        assert_eq!(nfa("(a*)*b").distinguish(&nfa("a*b")), None);
+        assert_eq!(nfa("abc|abd").distinguish(&nfa("ab[cd]")), None);
+        assert_eq!(nfa("(a|b)*").distinguish(&nfa("(a*b*)*")), None);
+        assert_eq!(nfa(".|a").distinguish(&nfa(".")), None);
+    }
+
+    #[test]
+    fn distinguishing_strings() {
+        assert_eq!(nfa("a*").distinguish(&nfa("a+")), Some(String::from("")));
+        assert_eq!(nfa("(ab)*").distinguish(&nfa("a*b*")), Some(String::from("a")));
+        assert_eq!(nfa("[a-z]x").distinguish(&nfa(".x")), Some(String::from("0x")));
+        assert_eq!(nfa("abc").distinguish(&nfa("abd")), Some(String::from("abc")));
+    }
+
+    #[test]
+    fn set_operators() {
+        let identifiers = nfa("[a-z]+ & ~(if|else|while)").to_dfa();
+        assert!(identifiers.accepts("iff"));
+        assert!(identifiers.accepts("x"));
+        assert!(!identifiers.accepts("if"));
+        assert!(!identifiers.accepts("else"));
+        assert!(!identifiers.accepts(""));
+
+        let difference = NFA::from_tokens(Tokenizer::extended("[a-z]+ - (if|else|while)")).unwrap();
+        assert_eq!(difference.distinguish(&nfa("[a-z]+ & ~(if|else|while)")), None);
+
+        let no_ab = nfa("~(.*ab.*)").to_dfa();
+        assert!(no_ab.accepts("ba"));
+        assert!(no_ab.accepts(""));
+        assert!(!no_ab.accepts("xaby"));
+
+        assert!(is_universal(&nfa("~(a&b)")).holds());
+        assert!(is_empty(&nfa(".*&~(.*)")).holds());
+        assert_eq!(nfa("~(a*)").distinguish(&nfa("a*[b-z].*")), Some(String::from("0")));
+    }
+
+    #[test]
+    fn embedded_automata() {
+        // the DFA for the intersection is built inside a larger Thompson NFA
+        let nfa = nfa("x(~(a*)&[a-c]+)y");
+        assert!(nfa.accepts("--xaby--"));
+        assert!(!nfa.accepts("xaay"));
+        assert!(!nfa.accepts("xy"));
+        let dfa = nfa.to_dfa();
+        for _ in 0..50 {
+            assert!(dfa.accepts(&nfa.random_regex()));
+        }
+    }
+
+    #[test]
+    fn shortest_matches() {
+        assert_eq!(nfa("ab+|c*d").shortest_match_string(), Some(String::from("d")));
+        assert_eq!(nfa("x*").shortest_match_string(), Some(String::from("")));
+        assert_eq!(nfa("a[]").shortest_match_string(), None);
+    }
+
+    #[test]
+    fn enumeration() {
+        let strings: Vec<String> = nfa("(a|b)*c").enumerate(3).collect();
+        assert_eq!(strings, vec!["c", "ac", "bc", "aac", "abc", "bac", "bbc"]);
+        let strings: Vec<String> = nfa("[x-z]|ab").enumerate(5).collect();
+        assert_eq!(strings, vec!["x", "y", "z", "ab"]);
+        assert_eq!(nfa("a*").enumerate(2).collect::<Vec<String>>(), vec!["", "a", "aa"]);
+        assert_eq!(nfa("a+").enumerate(0).count(), 0);
+        // far too many strings to gather up front, but they come one at a time,
+        // skipping over the surrogates that are not chars
+        assert_eq!(nfa(".*").enumerate(10).nth(100_000), Some(String::from("\u{18e9f}")));
+        // readable chars first, as in shortest_match_string
+        let strings: Vec<String> = nfa(".").enumerate(1).take(3).collect();
+        assert_eq!(strings, vec!["a", "b", "c"]);
+        let chars: String = nfa("[\0-~]").enumerate(1).skip(59).take(6).collect();
+        assert_eq!(chars, "XYZ !\"");
+        assert_eq!(nfa("[\0-~]").enumerate(1).nth(95), Some(String::from("\0")));
+        assert_eq!(nfa("[\0-~]").enumerate(1).count(), 127);
+        for pattern in &["ab+|c*d", ".x|[0-9]", "~(a*)"] {
+            assert_eq!(nfa(pattern).enumerate(5).next(), nfa(pattern).shortest_match_string());
+        }
+    }
+
+    #[test]
+    fn counting() {
+        assert_eq!(nfa("[ab]c|d.").count_strings(2), 2 + 62);
+        assert_eq!(nfa("(a|bb)*").count_strings(4), 5);
+        assert_eq!(nfa("(a|bb)*").count_strings(0), 1);
+        // a char named in the pattern can stand in for `.` as well
+        assert_eq!(nfa("é.").count_strings(2), 63);
+        assert_eq!(nfa("~(a)").sampler(1, &[('a', 'c')]).count(), 2);
+        assert_eq!(nfa("a+").count_strings(3), 1);
+        assert_eq!(nfa("a+").count_strings(0), 0);
+    }
+
+    #[test]
+    fn uniform_samples() {
+        use rand::prelude::*;
+        let mut rng = StdRng::seed_from_u64(7);
+        let sampler = nfa("(a|bb)*").sampler(4, ALPHANUMERIC);
+        let mut seen: HashMap<String, usize> = HashMap::new();
+        for _ in 0..5_000 {
+            *seen.entry(sampler.sample(&mut rng).unwrap()).or_insert(0) += 1;
+        }
+        assert_eq!(seen.len(), 5);
+        // each of the five strings should come up about a thousand times
+        assert!(seen.values().all(|count| 800 < *count && *count < 1200), "{:?}", seen);
+
+        let sampler = nfa("x.").sampler(2, &[('0', '1')]);
+        for _ in 0..20 {
+            let sample = sampler.sample(&mut rng).unwrap();
+            assert!(["x0", "x1", "xx"].contains(&sample.as_str()), "{}", sample);
+        }
+        assert_eq!(nfa("ab").sampler(3, ALPHANUMERIC).sample(&mut rng), None);
+    }
+
+    #[test]
+    fn complements() {
+        let complement = nfa("a|bc").complement();
+        assert!(!complement.to_dfa().accepts("a"));
+        assert!(!complement.to_dfa().accepts("bc"));
+        assert!(complement.to_dfa().accepts(""));
+        assert!(complement.to_dfa().accepts("abc"));
+        assert_eq!(complement.shortest_match_string(), Some(String::new()));
+        assert_eq!(nfa(".*").complement().shortest_match_string(), None);
+    }
+
+    #[test]
+    fn minimization() {
+        // the subset construction keeps apart the states after a and after c
+        let dfa = nfa("ab|cb").to_dfa();
+        let minimal = dfa.minimize();
+        assert_eq!((dfa.next.len(), minimal.next.len()), (5, 4));
+        for input in &["ab", "cb", "a", "b", "abb", ""] {
+            assert_eq!(minimal.accepts(input), dfa.accepts(input), "{:?}", input);
+        }
+        // a, b, accepting after b, and dead
+        assert_eq!(nfa("(a|aa)*b").to_dfa().minimize().next.len(), 3);
+        assert_eq!(nfa("a|b|c").to_dfa().minimize().next.len(), 3);
+    }
+
+    #[test]
+    fn subsets() {
+        assert!(is_subset(&nfa("ab+"), &nfa("a.*")).holds());
+        assert!(is_subset(&nfa("[b-d]x"), &nfa("(b|c|d)x")).holds());
+        assert!(is_subset(&nfa("a*"), &nfa("a*")).holds());
+        assert_eq!(is_subset(&nfa("a.*"), &nfa("ab+")), Verdict::No(String::from("a")));
+        assert_eq!(is_subset(&nfa("a*"), &nfa("a+")), Verdict::No(String::from("")));
+        assert_eq!(is_subset(&nfa("[a-z]+"), &nfa("[a-y]+")), Verdict::No(String::from("z")));
+    }
+
+    #[test]
+    fn emptiness() {
+        assert!(is_empty(&nfa("[]")).holds());
+        assert!(is_empty(&nfa("a[]b|[]*[]")).holds());
+        assert_eq!(is_empty(&nfa("x*")), Verdict::No(String::from("")));
+        assert_eq!(is_empty(&nfa("[]|ab+")), Verdict::No(String::from("ab")));
+    }
+
+    #[test]
+    fn universality() {
+        assert!(is_universal(&nfa(".*")).holds());
+        assert!(is_universal(&nfa("(.|a)*")).holds());
+        assert_eq!(is_universal(&nfa(".+")), Verdict::No(String::from("")));
+        assert_eq!(is_universal(&nfa("(a|b)*")), Verdict::No(String::from("0")));
+    }
+}
@@ -0,0 +1,184 @@
+use super::super::parser::simplify::simplify;
+use super::super::parser::{
+    build_alternation, build_catenation, build_class, build_closure, build_one_or_more, AST,
+};
+use std::collections::BTreeMap;
+
+/*
+ * Turning automata back into patterns by state elimination. Edges are
+ * labelled with patterns rather than chars, and states are taken out one
+ * at a time, each replaced by edges labelled with the paths that ran
+ * through it, until only one edge from a new start to a new end is left.
+ */
+
+/**
+ * What an edge of the automaton matches while states are eliminated: the
+ * empty string, or the strings of a pattern. The syntax has no way to
+ * write the empty string on its own, so it is kept apart until the end.
+ */
+#[derive(Clone)]
+enum Label {
+    Empty,
+    Pattern(AST),
+}
+
+/**
+ * The pattern for the strings that lead from the start state to some
+ * accepting state of an automaton with states numbered from 0, along edges
+ * labelled with patterns. An automaton that accepts nothing gives [], and
+ * one that only accepts the empty string gives []*.
+ */
+pub(super) fn eliminate(count: usize, start: usize, accepting: &[bool], edges: Vec<(usize, usize, AST)>) -> AST {
+    // the new start and end are numbered after the automaton's own states
+    let (entry, exit) = (count, count + 1);
+    let mut labels: BTreeMap<(usize, usize), Label> = BTreeMap::new();
+    add(&mut labels, entry, start, Label::Empty);
+    for (id, accepting) in accepting.iter().enumerate() {
+        if *accepting {
+            add(&mut labels, id, exit, Label::Empty);
+        }
+    }
+    for (from, to, ast) in edges {
+        add(&mut labels, from, to, Label::Pattern(ast));
+    }
+
+    let mut remaining: Vec<usize> = (0..count).collect();
+    while !remaining.is_empty() {
+        // the state with the fewest paths through it makes the fewest new edges
+        let paths = |q: usize| {
+            let ins = labels.keys().filter(|(from, to)| *to == q && *from != q).count();
+            let outs = labels.keys().filter(|(from, to)| *from == q && *to != q).count();
+            ins * outs
+        };
+        let at = (0..remaining.len()).min_by_key(|at| paths(remaining[*at])).unwrap();
+        let q = remaining.remove(at);
+
+        let around = labels.remove(&(q, q)).map(star);
+        let ins: Vec<((usize, usize), Label)> = labels
+            .iter()
+            .filter(|((_, to), _)| *to == q)
+            .map(|(edge, label)| (*edge, label.clone()))
+            .collect();
+        let outs: Vec<((usize, usize), Label)> = labels
+            .iter()
+            .filter(|((from, _), _)| *from == q)
+            .map(|(edge, label)| (*edge, label.clone()))
+            .collect();
+        for (edge, _) in ins.iter().chain(&outs) {
+            labels.remove(edge);
+        }
+        for ((p, _), into) in &ins {
+            for ((_, r), out) in &outs {
+                let through = match &around {
+                    Some(around) => catenate(into.clone(), around.clone()),
+                    None => into.clone(),
+                };
+                add(&mut labels, *p, *r, catenate(through, out.clone()));
+            }
+        }
+    }
+
+    match labels.remove(&(entry, exit)) {
+        None => build_class(vec![]),
+        Some(Label::Empty) => build_closure(build_class(vec![])),
+        Some(Label::Pattern(ast)) => simplify(ast),
+    }
+}
+
+/**
+ * Add an edge, as another alternative if there is one already.
+ */
+fn add(labels: &mut BTreeMap<(usize, usize), Label>, from: usize, to: usize, label: Label) {
+    let label = match labels.remove(&(from, to)) {
+        Some(existing) => alternate(existing, label),
+        None => label,
+    };
+    labels.insert((from, to), label);
+}
+
+fn alternate(lhs: Label, rhs: Label) -> Label {
+    match (lhs, rhs) {
+        (Label::Empty, Label::Empty) => Label::Empty,
+        (Label::Empty, Label::Pattern(ast)) | (Label::Pattern(ast), Label::Empty) => {
+            Label::Pattern(optional(ast))
+        }
+        (Label::Pattern(lhs), Label::Pattern(rhs)) if lhs == rhs => Label::Pattern(lhs),
+        (Label::Pattern(lhs), Label::Pattern(rhs)) => Label::Pattern(build_alternation(lhs, rhs)),
+    }
+}
+
+/**
+ * A pattern that also matches the empty string.
+ */
+fn optional(ast: AST) -> AST {
+    match ast {
+        AST::Closure(_) => ast,
+        AST::OneOrMore(inner) => build_closure(*inner),
+        ast => build_alternation(ast, build_closure(build_class(vec![]))),
+    }
+}
+
+/**
+ * One label followed by another, where x followed by x* or x* followed by
+ * x is written x+.
+ */
+fn catenate(lhs: Label, rhs: Label) -> Label {
+    match (lhs, rhs) {
+        (Label::Empty, label) | (label, Label::Empty) => label,
+        (Label::Pattern(lhs), Label::Pattern(AST::Closure(inner))) if lhs == *inner => {
+            Label::Pattern(build_one_or_more(lhs))
+        }
+        (Label::Pattern(AST::Closure(inner)), Label::Pattern(rhs)) if rhs == *inner => {
+            Label::Pattern(build_one_or_more(rhs))
+        }
+        (Label::Pattern(lhs), Label::Pattern(rhs)) => Label::Pattern(build_catenation(lhs, rhs)),
+    }
+}
+
+fn star(label: Label) -> Label {
+    match label {
+        Label::Empty => Label::Empty,
+        Label::Pattern(AST::OneOrMore(inner)) => Label::Pattern(build_closure(*inner)),
+        Label::Pattern(ast @ AST::Closure(_)) => Label::Pattern(ast),
+        Label::Pattern(ast) => Label::Pattern(build_closure(ast)),
+    }
+}
+
+#[cfg(test)]
+mod eliminate_tests {
+    use crate::nfa::NFA;
+    use crate::tokenizer::Tokenizer;
+
+    // rewrite a pattern by way of its DFA, checking nothing about it changes
+    fn rewritten(pattern: &str) -> String {
+        let nfa = NFA::from_tokens(Tokenizer::extended(pattern)).unwrap();
+        let rewritten = nfa.to_ast().to_string();
+        let again = NFA::from(&rewritten).unwrap();
+        assert_eq!(nfa.distinguish(&again), None, "{} became {}", pattern, rewritten);
+        rewritten
+    }
+
+    #[test]
+    fn plain_patterns() {
+        assert_eq!(rewritten("abc"), "abc");
+        assert_eq!(rewritten("ab|ac"), "a[bc]");
+        assert_eq!(rewritten("(a|b)*"), "[ab]*");
+        assert_eq!(rewritten("a+"), "a+");
+        assert_eq!(rewritten("a*|b"), "a*|b");
+    }
+
+    #[test]
+    fn set_operators() {
+        assert_eq!(rewritten("[a-z]+&..."), "[a-z][a-z][a-z]");
+        assert_eq!(rewritten("(a|b|c)-b"), "[ac]");
+        rewritten("a*&~(aa)");
+        rewritten("~(a)");
+        rewritten("(ab)*-(abab)");
+    }
+
+    #[test]
+    fn nothing_and_the_empty_string() {
+        assert_eq!(rewritten("a[]"), "[]");
+        assert_eq!(rewritten("a*&b*"), "[]*");
+    }
+}
@@ -0,0 +1,585 @@
+use super::super::parser::normalize_ranges;
+use super::super::tokenizer::{Token, Tokenizer};
+use super::ambiguity::components;
+use super::dfa::{nth_char, size, Sampler, ALPHANUMERIC, DFA};
+use super::State::*;
+use super::{Char, StateId, NFA};
+use rand::prelude::*;
+use std::collections::{HashSet, VecDeque};
+
+/*
+ * Random strings a pattern matches, for thegrep -g. Given a seed, the same
+ * pattern and settings generate the same strings on every run.
+ */
+
+/**
+ * How many times in a row a unique generator may come up with a string it
+ * already gave before it decides there are no more.
+ */
+const GIVE_UP: usize = 1_000;
+
+/**
+ * Settings for generating random strings.
+ */
+#[derive(Debug, Clone)]
+pub struct GenConfig {
+    // None seeds the generator differently every run
+    pub seed: Option<u64>,
+    // the longest string to generate
+    pub max_len: Option<usize>,
+    // generate only strings of exactly this length, each as likely as any other
+    pub length: Option<usize>,
+    // the chars `.` stands for, besides those the pattern names itself
+    pub alphabet: Vec<(char, char)>,
+    // the chance of leaving a * or + loop each time around it
+    pub stop: f64,
+    // never generate the same string twice
+    pub unique: bool,
+}
+
+impl Default for GenConfig {
+    fn default() -> GenConfig {
+        GenConfig {
+            seed: None,
+            max_len: None,
+            length: None,
+            alphabet: ALPHANUMERIC.to_vec(),
+            stop: 0.5,
+            unique: false,
+        }
+    }
+}
+
+/**
+ * Read an alphabet written the way the inside of a [class] is, such as
+ * a-z0-9_.
+ */
+pub fn parse_alphabet(spec: &str) -> Result<Vec<(char, char)>, String> {
+    let pattern = format!("[{}]", spec);
+    let tokens: Vec<Token> = Tokenizer::new(&pattern).collect();
+    match tokens.as_slice() {
+        [Token::Class(ranges)] if !ranges.is_empty() => Ok(normalize_ranges(ranges.clone())),
+        _ => Err(format!(
+            "alphabet {:?} should be written like the inside of a [class], such as a-z0-9",
+            spec
+        )),
+    }
+}
+
+/**
+ * An endless supply of random strings an NFA matches, from NFA::generator.
+ * A unique generator runs dry once it stops finding new strings.
+ */
+pub struct Generator<'a> {
+    nfa: &'a NFA,
+    rng: StdRng,
+    strategy: Strategy,
+    seen: Option<HashSet<String>>,
+}
+
+/**
+ * An endless supply of random strings an NFA does not match anywhere in
+ * them, so thegrep passes over them as lines, from NFA::near_misses. Most
+ * are matching strings with one small change made to them: a char
+ * inserted, deleted or replaced, or the end cut off. When a change leaves a
+ * match in the string, one is taken from the complement of the pattern
+ * matched anywhere instead.
+ */
+pub struct NearMisses<'a> {
+    positives: Option<Generator<'a>>,
+    complement: NFA,
+    strategy: Strategy,
+    // the strings with a match anywhere in them, to check each change against
+    dfa: DFA,
+    universe: Vec<(char, char)>,
+    length: Option<usize>,
+    max_len: Option<usize>,
+    seen: Option<HashSet<String>>,
+    rng: StdRng,
+}
+
+enum Strategy {
+    Walk(Walk),
+    Sample(Sampler),
+}
+
+impl Strategy {
+    fn generate<R: Rng>(&self, nfa: &NFA, rng: &mut R) -> String {
+        match self {
+            Strategy::Walk(walk) => walk.generate(nfa, rng),
+            Strategy::Sample(sampler) => sampler.sample(rng).unwrap(),
+        }
+    }
+}
+
+/**
+ * What a random walk from the start of an NFA to its end needs to know to
+ * stay within a length limit and leave its loops.
+ */
+struct Walk {
+    max_len: Option<usize>,
+    stop: f64,
+    // the fewest chars it takes to get from each state to the end
+    remaining: Vec<usize>,
+    // the splits that either go around a loop again or leave it
+    loops: Vec<bool>,
+    // the chars a string may be made of
+    universe: Vec<(char, char)>,
+}
+
+impl NFA {
+    /**
+     * A random string the NFA matches, generated with the default settings.
+     */
+    pub fn random_regex(&self) -> String {
+        self.generator(&GenConfig::default())
+            .and_then(|mut generator| generator.next().ok_or_else(String::new))
+            .unwrap_or_default()
+    }
+
+    /**
+     * Set up to generate random strings the NFA matches. This fails if the
+     * settings make no sense or no string fits within them.
+     */
+    pub fn generator(&self, config: &GenConfig) -> Result<Generator<'_>, String> {
+        Ok(Generator {
+            nfa: self,
+            rng: seeded(config.seed),
+            strategy: self.strategy(config)?,
+            seen: if config.unique { Some(HashSet::new()) } else { None },
+        })
+    }
+
+    /**
+     * Set up to generate random strings the NFA does not match anywhere in
+     * them, within the same settings as NFA::generator. This fails if every
+     * string that fits them has a match in it.
+     */
+    pub fn near_misses(&self, config: &GenConfig) -> Result<NearMisses<'_>, String> {
+        let anywhere = self.anywhere();
+        let complement = anywhere.complement();
+        // the complement matching nothing within the settings means the
+        // pattern matches everything within them
+        let strategy = complement.strategy(config).map_err(|msg| {
+            if msg.starts_with("the pattern matches no") {
+                msg.replacen("no", "all", 1)
+            } else {
+                msg
+            }
+        })?;
+        // mutations are made to matching strings of any length that fits
+        let positives = GenConfig {
+            seed: Some(seeded(config.seed).gen()),
+            length: None,
+            unique: false,
+            ..config.clone()
+        };
+        let mut universe = config.alphabet.clone();
+        universe.extend(&self.named);
+        Ok(NearMisses {
+            positives: self.generator(&positives).ok(),
+            complement,
+            strategy,
+            dfa: anywhere.to_dfa(),
+            universe: normalize_ranges(universe),
+            length: config.length,
+            max_len: config.max_len,
+            seen: if config.unique { Some(HashSet::new()) } else { None },
+            rng: seeded(config.seed),
+        })
+    }
+
+    /**
+     * Work out how to generate the strings the NFA matches within the
+     * settings, or why there are none.
+     */
+    fn strategy(&self, config: &GenConfig) -> Result<Strategy, String> {
+        if !(config.stop > 0.0 && config.stop <= 1.0) {
+            return Err(format!("stop probability {} should be above 0 and at most 1", config.stop));
+        }
+        if config.alphabet.is_empty() {
+            return Err(String::from("the alphabet for . is empty"));
+        }
+
+        Ok(match (config.length, config.max_len) {
+            (Some(length), Some(max_len)) if length > max_len => {
+                return Err(format!("length {} is over the maximum length {}", length, max_len));
+            }
+            (Some(length), _) => {
+                let sampler = self.sampler(length, &config.alphabet);
+                if sampler.count() == 0 {
+                    return Err(format!("the pattern matches no strings of length {}", length));
+                }
+                Strategy::Sample(sampler)
+            }
+            (None, max_len) => {
+                let walk = self.walk(config);
+                match (walk.remaining[self.start], max_len) {
+                    (usize::MAX, _) => return Err(String::from("the pattern matches no strings")),
+                    (shortest, Some(max_len)) if shortest > max_len => {
+                        return Err(format!("the pattern matches no strings of at most {} chars", max_len));
+                    }
+                    _ => Strategy::Walk(walk),
+                }
+            }
+        })
+    }
+
+    fn walk(&self, config: &GenConfig) -> Walk {
+        let n = self.states.len();
+        let mut from: Vec<Vec<(StateId, usize)>> = vec![vec![]; n];
+        for id in 0..n {
+            for (to, weight) in self.edges(id) {
+                from[to].push((id, weight));
+            }
+        }
+
+        // breadth first back from the end, with epsilon edges at the front of the queue
+        let mut remaining = vec![usize::MAX; n];
+        let mut queue: VecDeque<StateId> = (0..n).filter(|id| matches!(self.states[*id], End)).collect();
+        for id in &queue {
+            remaining[*id] = 0;
+        }
+        while let Some(id) = queue.pop_front() {
+            for (previous, weight) in &from[id] {
+                if remaining[id] + weight < remaining[*previous] {
+                    remaining[*previous] = remaining[id] + weight;
+                    if *weight == 0 {
+                        queue.push_front(*previous);
+                    } else {
+                        queue.push_back(*previous);
+                    }
+                }
+            }
+        }
+
+        // a loop's split has its body on the left, within the loop, and its
+        // way out on the right
+        let component = components(n, |id| self.edges(id).into_iter().map(|(to, _)| to).collect());
+        let loops = (0..n)
+            .map(|id| match self.states[id] {
+                Split(Some(lhs), Some(rhs)) => {
+                    component[lhs] == component[id] && component[rhs] != component[id]
+                }
+                _ => false,
+            })
+            .collect();
+
+        let mut universe = config.alphabet.clone();
+        universe.extend(&self.named);
+        Walk {
+            max_len: config.max_len,
+            stop: config.stop,
+            remaining,
+            loops,
+            universe: normalize_ranges(universe),
+        }
+    }
+}
+
+fn seeded(seed: Option<u64>) -> StdRng {
+    match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    }
+}
+
+impl Walk {
+    /**
+     * Walk from the start of the NFA to its end, only ever taking a step
+     * that leaves enough room to get to the end within the length limit.
+     */
+    fn generate<R: Rng>(&self, nfa: &NFA, rng: &mut R) -> String {
+        let mut string = String::new();
+        let mut used = 0;
+        let mut id = nfa.start;
+        loop {
+            let left = self.max_len.map_or(usize::MAX, |max_len| max_len - used);
+            let fits = |to: StateId, weight: usize| {
+                self.remaining[to] != usize::MAX && self.remaining[to] + weight <= left
+            };
+            id = match &nfa.states[id] {
+                End => return string,
+                Start(next) | Epsilon(next) => next.unwrap(),
+                Split(lhs, rhs) => {
+                    let (lhs, rhs) = (lhs.unwrap(), rhs.unwrap());
+                    match (fits(lhs, 0), fits(rhs, 0)) {
+                        (true, true) if self.loops[id] => {
+                            if rng.gen_bool(self.stop) {
+                                rhs
+                            } else {
+                                lhs
+                            }
+                        }
+                        (true, true) => {
+                            if rng.gen() {
+                                lhs
+                            } else {
+                                rhs
+                            }
+                        }
+                        (true, false) => lhs,
+                        _ => rhs,
+                    }
+                }
+                Match(c, next) => {
+                    string.push(self.pick(c, rng));
+                    used += 1;
+                    next.unwrap()
+                }
+            };
+        }
+    }
+
+    /**
+     * A random char a Match edge consumes, as likely as any other. Sets too
+     * big to spell out, such as what is left after a complement, are cut
+     * down to the universe where that leaves anything.
+     */
+    fn pick<R: Rng>(&self, c: &Char, rng: &mut R) -> char {
+        let ranges = match c {
+            Char::Literal(c) => return *c,
+            Char::Any => self.universe.clone(),
+            Char::Set(ranges) => {
+                let within: Vec<(char, char)> = ranges
+                    .iter()
+                    .flat_map(|(start, end)| {
+                        self.universe
+                            .iter()
+                            .filter(move |(s, e)| s <= end && start <= e)
+                            .map(move |(s, e)| (*start.max(s), *end.min(e)))
+                    })
+                    .collect();
+                if within.is_empty() {
+                    ranges.clone()
+                } else {
+                    within
+                }
+            }
+        };
+        nth_char(&ranges, rng.gen_range(0, size(&ranges)))
+    }
+}
+
+impl<'a> Iterator for Generator<'a> {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        for _ in 0..GIVE_UP {
+            let string = self.strategy.generate(self.nfa, &mut self.rng);
+            if fresh(&mut self.seen, &string) {
+                return Some(string);
+            }
+        }
+        None
+    }
+}
+
+impl<'a> NearMisses<'a> {
+    /**
+     * A matching string with one random change made to it.
+     */
+    fn mutate(&mut self) -> Option<String> {
+        let mut chars: Vec<char> = self.positives.as_mut()?.next()?.chars().collect();
+        let any = nth_char(&self.universe, self.rng.gen_range(0, size(&self.universe)));
+        let change = if chars.is_empty() { 0 } else { self.rng.gen_range(0, 4) };
+        match change {
+            0 => chars.insert(self.rng.gen_range(0, chars.len() + 1), any),
+            1 => {
+                chars.remove(self.rng.gen_range(0, chars.len()));
+            }
+            2 => {
+                let at = self.rng.gen_range(0, chars.len());
+                chars[at] = any;
+            }
+            _ => chars.truncate(self.rng.gen_range(0, chars.len())),
+        }
+        Some(chars.into_iter().collect())
+    }
+
+    fn fits(&self, string: &str) -> bool {
+        let len = string.chars().count();
+        self.length.is_none_or(|length| len == length) && self.max_len.is_none_or(|max_len| len <= max_len)
+    }
+}
+
+impl<'a> Iterator for NearMisses<'a> {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        for _ in 0..GIVE_UP {
+            let string = match self.mutate() {
+                Some(string) if self.fits(&string) && !self.dfa.accepts(&string) => string,
+                _ => self.strategy.generate(&self.complement, &mut self.rng),
+            };
+            if fresh(&mut self.seen, &string) {
+                return Some(string);
+            }
+        }
+        None
+    }
+}
+
+/**
+ * Whether a string has not been generated before, if that matters.
+ */
+fn fresh(seen: &mut Option<HashSet<String>>, string: &str) -> bool {
+    match seen {
+        None => true,
+        Some(seen) => seen.insert(string.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod generate_tests {
+    use super::*;
+
+    fn generate(pattern: &str, config: &GenConfig, count: usize) -> Vec<String> {
+        let nfa = NFA::from(pattern).unwrap();
+        let strings: Vec<String> = nfa.generator(config).unwrap().take(count).collect();
+        let dfa = nfa.to_dfa();
+        for string in &strings {
+            assert!(dfa.accepts(string), "{:?} does not match {}", string, pattern);
+        }
+        strings
+    }
+
+    fn seeded(seed: u64) -> GenConfig {
+        GenConfig {
+            seed: Some(seed),
+            ..GenConfig::default()
+        }
+    }
+
+    #[test]
+    fn reproducible() {
+        let pattern = "(ab|c.)*[x-z]+";
+        assert_eq!(generate(pattern, &seeded(42), 20), generate(pattern, &seeded(42), 20));
+        assert_ne!(generate(pattern, &seeded(42), 20), generate(pattern, &seeded(43), 20));
+    }
+
+    #[test]
+    fn bounded() {
+        let config = GenConfig {
+            max_len: Some(5),
+            stop: 0.01,
+            ..seeded(1)
+        };
+        for string in generate("a(bc)*d.*", &config, 100) {
+            assert!(string.chars().count() <= 5, "{:?}", string);
+        }
+        let nfa = NFA::from("abcdef").unwrap();
+        assert!(nfa.generator(&config).is_err());
+    }
+
+    #[test]
+    fn alphabets() {
+        let config = GenConfig {
+            alphabet: parse_alphabet("01").unwrap(),
+            ..seeded(3)
+        };
+        for string in generate("x.+", &config, 50) {
+            assert!(string[1..].chars().all(|c| "01x".contains(c)), "{:?}", string);
+        }
+        assert_eq!(parse_alphabet("a-c_").unwrap(), vec![('_', '_'), ('a', 'c')]);
+        assert!(parse_alphabet("").is_err());
+        assert!(parse_alphabet("a]b").is_err());
+    }
+
+    #[test]
+    fn complements_stay_in_the_universe() {
+        for string in generate("~(a*)", &seeded(5), 50) {
+            assert!(string.chars().all(|c| c.is_ascii_alphanumeric()), "{:?}", string);
+        }
+    }
+
+    #[test]
+    fn unique() {
+        let config = GenConfig {
+            unique: true,
+            ..seeded(9)
+        };
+        let mut strings = generate("[ab][ab]", &config, 10);
+        assert_eq!(strings.len(), 4);
+        strings.sort();
+        assert_eq!(strings, vec!["aa", "ab", "ba", "bb"]);
+    }
+
+    #[test]
+    fn exact_lengths() {
+        let config = GenConfig {
+            length: Some(3),
+            ..seeded(11)
+        };
+        for string in generate("a*b*", &config, 20) {
+            assert_eq!(string.len(), 3);
+        }
+        let nfa = NFA::from("ab").unwrap();
+        assert!(nfa.generator(&config).is_err());
+    }
+
+    fn misses(pattern: &str, config: &GenConfig, count: usize) -> Vec<String> {
+        let nfa = NFA::from(pattern).unwrap();
+        let strings: Vec<String> = nfa.near_misses(config).unwrap().take(count).collect();
+        for string in &strings {
+            assert!(!nfa.accepts(string), "{:?} matches {}", string, pattern);
+        }
+        strings
+    }
+
+    #[test]
+    fn near_misses() {
+        let strings = misses("[a-z]+@[a-z]+\\.com", &seeded(13), 200);
+        assert_eq!(strings.len(), 200);
+        // most are only one change away from matching, so still look the part
+        assert!(strings.iter().filter(|string| string.contains('@')).count() > 100);
+        assert_eq!(misses("(ab)+", &seeded(13), 50), misses("(ab)+", &seeded(13), 50));
+        // no match anywhere, not just not a whole match
+        for string in misses("abc", &seeded(3), 200) {
+            assert!(!string.contains("abc"), "{:?}", string);
+        }
+    }
+
+    #[test]
+    fn bounded_misses() {
+        let config = GenConfig {
+            max_len: Some(3),
+            ..seeded(17)
+        };
+        for string in misses("a+b", &config, 100) {
+            assert!(string.chars().count() <= 3, "{:?}", string);
+        }
+        let config = GenConfig {
+            length: Some(2),
+            unique: true,
+            alphabet: parse_alphabet("ab").unwrap(),
+            ..seeded(17)
+        };
+        let mut strings = misses("ab", &config, 10);
+        strings.sort();
+        assert_eq!(strings, vec!["aa", "ba", "bb"]);
+    }
+
+    #[test]
+    fn nothing_to_miss() {
+        for pattern in &[".*", "(ab)*"] {
+            let nfa = NFA::from(pattern).unwrap();
+            assert!(nfa.near_misses(&GenConfig::default()).is_err());
+        }
+        // a pattern matching nothing misses everything
+        assert_eq!(misses("a[]", &seeded(19), 5).len(), 5);
+    }
+
+    #[test]
+    fn settings_that_make_no_sense() {
+        let nfa = NFA::from("a").unwrap();
+        for stop in &[0.0, 1.5] {
+            let config = GenConfig {
+                stop: *stop,
+                ..GenConfig::default()
+            };
+            assert!(nfa.generator(&config).is_err());
+        }
+        assert!(NFA::from("a[]").unwrap().generator(&GenConfig::default()).is_err());
+    }
+}
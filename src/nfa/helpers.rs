@@ -2,7 +2,7 @@ use super::NFA;
 use super::Char;
 use super::State::*;
 
-/**
+/*
  * Helper functions for visualizing our NFA
  * Both at the internal representation level and in dot format
  * to generate a graphical representation.
@@ -32,6 +32,7 @@ pub fn nfa_dot(nfa: &NFA) -> String {
                 "\t{0} -> {1} [label=\"ε\"]\n\t{0} -> {2} [label=\"ε\"]\n",
                 id, rhs, lhs
             ),
+            Epsilon(Some(next)) => format!("\t{} -> {} [label=\"ε\"]\n", id, next),
             End => format!("\t{} [shape=\"doublecircle\"]\n", id),
             _ => String::new(),
         });
@@ -48,6 +49,19 @@ impl std::fmt::Display for Char {
         match self {
             Char::Literal(c) => write!(f, "{}", c),
             Char::Any => write!(f, "ANY"),
+            Char::Set(ranges) => {
+                for (i, (start, end)) in ranges.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    if start == end {
+                        write!(f, "{}", start)?;
+                    } else {
+                        write!(f, "{}-{}", start, end)?;
+                    }
+                }
+                Ok(())
+            }
         }
     }
 }
@@ -0,0 +1,269 @@
+use super::super::parser::{build_char, build_class, normalize_ranges, AST};
+use super::eliminate::eliminate;
+use std::collections::{BTreeMap, VecDeque};
+
+/*
+ * Inferring a pattern from example lines, for thegrep --learn. A DFA is
+ * learned by RPNI: start from a tree with a state for every prefix of the
+ * examples, then merge states, shortest prefixes first, wherever that does
+ * not lead a rejected string to acceptance. The DFA is then turned into a
+ * pattern by state elimination.
+ */
+
+/**
+ * A partly merged tree of prefixes, with each state known to be accepting,
+ * known to be rejecting, or not yet known.
+ */
+struct Automaton {
+    next: Vec<BTreeMap<char, usize>>,
+    label: Vec<Option<bool>>,
+}
+
+// a change made while merging, kept so that a failed merge can be taken back
+enum Undo {
+    Label(usize, Option<bool>),
+    Edge(usize, char, Option<usize>),
+}
+
+/**
+ * A pattern that finds a match in every positive line and in no negative
+ * one. Since thegrep looks for a match anywhere in a line, not only the
+ * negative lines but every piece of them must be rejected; a positive line
+ * found within a negative one makes that impossible.
+ */
+pub fn learn(positives: &[String], negatives: &[String]) -> Result<AST, String> {
+    for positive in positives {
+        if let Some(negative) = negatives.iter().find(|negative| negative.contains(positive.as_str())) {
+            return Err(format!(
+                "positive example {:?} is found within negative example {:?}",
+                positive, negative
+            ));
+        }
+    }
+
+    let mut tree = Automaton::new();
+    for positive in positives {
+        let end = tree.insert(positive.chars(), &mut |_| {});
+        tree.label[end] = Some(true);
+    }
+    for negative in negatives {
+        let chars: Vec<char> = negative.chars().collect();
+        for start in 0..=chars.len() {
+            let mut label = vec![];
+            tree.insert(chars[start..].iter().cloned(), &mut |id| label.push(id));
+            for id in label {
+                tree.label[id] = Some(false);
+            }
+        }
+    }
+    let mut dfa = tree.in_shortlex_order();
+
+    let mut red = vec![0];
+    let mut is_red = vec![false; dfa.next.len()];
+    is_red[0] = true;
+    loop {
+        let blue = red
+            .iter()
+            .flat_map(|id| dfa.next[*id].values())
+            .filter(|id| !is_red[**id])
+            .min()
+            .cloned();
+        let blue = match blue {
+            Some(blue) => blue,
+            None => break,
+        };
+        if !red.iter().any(|id| dfa.merge(&red, *id, blue)) {
+            red.push(blue);
+            red.sort();
+            is_red[blue] = true;
+        }
+    }
+
+    // the red states are all that can still be reached
+    let index: BTreeMap<usize, usize> = red.iter().enumerate().map(|(at, id)| (*id, at)).collect();
+    let accepting: Vec<bool> = red.iter().map(|id| dfa.label[*id] == Some(true)).collect();
+    let mut edges = vec![];
+    for id in &red {
+        let mut targets: BTreeMap<usize, Vec<(char, char)>> = BTreeMap::new();
+        for (c, to) in &dfa.next[*id] {
+            targets.entry(index[to]).or_default().push((*c, *c));
+        }
+        for (to, ranges) in targets {
+            let ranges = normalize_ranges(ranges);
+            let label = match ranges.as_slice() {
+                [(start, end)] if start == end => build_char(*start),
+                _ => build_class(ranges),
+            };
+            edges.push((index[id], to, label));
+        }
+    }
+    Ok(eliminate(red.len(), 0, &accepting, edges))
+}
+
+impl Automaton {
+    fn new() -> Automaton {
+        Automaton {
+            next: vec![BTreeMap::new()],
+            label: vec![None],
+        }
+    }
+
+    /**
+     * Add a path for a string to the tree, calling visit on every state
+     * along it, the root included, and get the state it ends at.
+     */
+    fn insert<I: Iterator<Item = char>, F: FnMut(usize)>(&mut self, chars: I, visit: &mut F) -> usize {
+        let mut at = 0;
+        visit(at);
+        for c in chars {
+            at = match self.next[at].get(&c) {
+                Some(to) => *to,
+                None => {
+                    self.next.push(BTreeMap::new());
+                    self.label.push(None);
+                    let to = self.next.len() - 1;
+                    self.next[at].insert(c, to);
+                    to
+                }
+            };
+            visit(at);
+        }
+        at
+    }
+
+    /**
+     * The same tree renumbered breadth first, children in order of their
+     * chars, so that states are numbered in shortlex order of their prefixes.
+     */
+    fn in_shortlex_order(self) -> Automaton {
+        let mut order = vec![];
+        let mut queue = VecDeque::new();
+        queue.push_back(0);
+        while let Some(id) = queue.pop_front() {
+            order.push(id);
+            queue.extend(self.next[id].values());
+        }
+        let mut rank = vec![0; self.next.len()];
+        for (at, id) in order.iter().enumerate() {
+            rank[*id] = at;
+        }
+        Automaton {
+            next: order
+                .iter()
+                .map(|id| self.next[*id].iter().map(|(c, to)| (*c, rank[*to])).collect())
+                .collect(),
+            label: order.iter().map(|id| self.label[*id]).collect(),
+        }
+    }
+
+    /**
+     * Merge a blue state, the root of a part of the tree no merge has
+     * touched yet, into a red one, folding its subtree in so the automaton
+     * stays deterministic. If that would make a state both accepting and
+     * rejecting, the merge is taken back and false returned.
+     */
+    fn merge(&mut self, red: &[usize], into: usize, blue: usize) -> bool {
+        let mut undo = vec![];
+        let (parent, c) = red
+            .iter()
+            .flat_map(|id| self.next[*id].iter().map(move |(c, to)| (*id, *c, *to)))
+            .find(|(_, _, to)| *to == blue)
+            .map(|(id, c, _)| (id, c))
+            .unwrap();
+        self.set_edge(&mut undo, parent, c, into);
+
+        let mut pending = vec![(into, blue)];
+        while let Some((into, from)) = pending.pop() {
+            if into == from {
+                continue;
+            }
+            match (self.label[into], self.label[from]) {
+                (Some(lhs), Some(rhs)) if lhs != rhs => {
+                    self.undo(undo);
+                    return false;
+                }
+                (None, Some(label)) => {
+                    undo.push(Undo::Label(into, None));
+                    self.label[into] = Some(label);
+                }
+                _ => {}
+            }
+            let children: Vec<(char, usize)> = self.next[from].iter().map(|(c, to)| (*c, *to)).collect();
+            for (c, to) in children {
+                match self.next[into].get(&c) {
+                    Some(existing) => pending.push((*existing, to)),
+                    None => self.set_edge(&mut undo, into, c, to),
+                }
+            }
+        }
+        true
+    }
+
+    fn set_edge(&mut self, undo: &mut Vec<Undo>, from: usize, c: char, to: usize) {
+        let old = self.next[from].insert(c, to);
+        undo.push(Undo::Edge(from, c, old));
+    }
+
+    fn undo(&mut self, undo: Vec<Undo>) {
+        for change in undo.into_iter().rev() {
+            match change {
+                Undo::Label(id, label) => self.label[id] = label,
+                Undo::Edge(from, c, Some(to)) => {
+                    self.next[from].insert(c, to);
+                }
+                Undo::Edge(from, c, None) => {
+                    self.next[from].remove(&c);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod learn_tests {
+    use super::*;
+    use crate::nfa::NFA;
+
+    fn lines(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|line| line.to_string()).collect()
+    }
+
+    // learn a pattern and check thegrep would print exactly the positive lines
+    fn learned(positives: &[&str], negatives: &[&str]) -> String {
+        let pattern = learn(&lines(positives), &lines(negatives)).unwrap().to_string();
+        let nfa = NFA::from(&pattern).unwrap();
+        for positive in positives {
+            assert!(nfa.accepts(positive), "{} misses {:?}", pattern, positive);
+        }
+        for negative in negatives {
+            assert!(!nfa.accepts(negative), "{} matches {:?}", pattern, negative);
+        }
+        pattern
+    }
+
+    #[test]
+    fn generalizes() {
+        assert_eq!(learned(&["a", "aa", "aaa"], &["", "b"]), "b*aa*");
+        learned(&["ab", "abab", "ababab"], &["a", "b", "ba", "aa", "bb"]);
+    }
+
+    #[test]
+    fn ids() {
+        learned(
+            &["ID-12", "ID-345", "ID-6"],
+            &["ID-", "ID-x1", "id-12", "ID12", "xx-99"],
+        );
+    }
+
+    #[test]
+    fn without_negatives() {
+        // nothing is ruled out, so any mix of the chars seen is allowed
+        assert_eq!(learned(&["abc", "xyz"], &[]), "[a-cx-z]*");
+        assert_eq!(learned(&[], &["a"]), "[]");
+    }
+
+    #[test]
+    fn contradictions() {
+        assert!(learn(&lines(&["b"]), &lines(&["abc"])).is_err());
+    }
+}
@@ -0,0 +1,194 @@
+use super::ambiguity::components;
+use super::State::*;
+use super::{Char, StateId, NFA};
+use std::collections::VecDeque;
+
+/*
+ * How long the strings an NFA matches can be, found from the shape of its
+ * graph: each Match edge consumes one char and every other edge none.
+ */
+
+impl NFA {
+    /**
+     * The length of the shortest string the NFA matches, or usize::MAX if
+     * it matches none, since then no line is long enough.
+     */
+    pub fn min_length(&self) -> usize {
+        // breadth first, with epsilon edges at the front of the queue
+        let mut distance = vec![usize::MAX; self.states.len()];
+        let mut queue = VecDeque::new();
+        distance[self.start] = 0;
+        queue.push_back(self.start);
+        while let Some(id) = queue.pop_front() {
+            if let End = self.states[id] {
+                return distance[id];
+            }
+            for (to, weight) in self.edges(id) {
+                if distance[id] + weight < distance[to] {
+                    distance[to] = distance[id] + weight;
+                    if weight == 0 {
+                        queue.push_front(to);
+                    } else {
+                        queue.push_back(to);
+                    }
+                }
+            }
+        }
+        usize::MAX
+    }
+
+    /**
+     * The length of the longest string the NFA matches, or None if there is
+     * no limit. An NFA that matches nothing gives Some(0).
+     */
+    pub fn max_length(&self) -> Option<usize> {
+        let useful = self.useful_states();
+        let component = components(self.states.len(), |id| {
+            if !useful[id] {
+                return vec![];
+            }
+            self.edges(id)
+                .into_iter()
+                .map(|(to, _)| to)
+                .filter(|to| useful[*to])
+                .collect()
+        });
+
+        // a char consumed within a component can be consumed over and over
+        let count = component.iter().max().map_or(0, |max| max + 1);
+        let mut members: Vec<Vec<StateId>> = vec![vec![]; count];
+        for (id, c) in component.iter().enumerate() {
+            if useful[id] {
+                members[*c].push(id);
+            }
+        }
+
+        // every edge leaving a component leads to a lower numbered one, so
+        // the components are finished in increasing order
+        let mut longest: Vec<Option<usize>> = vec![None; count];
+        for c in 0..count {
+            for id in &members[c] {
+                if let End = self.states[*id] {
+                    longest[c] = Some(longest[c].unwrap_or(0));
+                }
+                for (to, weight) in self.edges(*id) {
+                    if !useful[to] {
+                        continue;
+                    }
+                    if component[to] == c {
+                        if weight > 0 {
+                            return None;
+                        }
+                        continue;
+                    }
+                    if let Some(rest) = longest[component[to]] {
+                        longest[c] = Some(longest[c].unwrap_or(0).max(rest + weight));
+                    }
+                }
+            }
+        }
+        Some(if useful[self.start] {
+            longest[component[self.start]].unwrap_or(0)
+        } else {
+            0
+        })
+    }
+
+    /**
+     * Whether the NFA matches the empty string, and so every line.
+     */
+    pub fn matches_empty(&self) -> bool {
+        self.min_length() == 0
+    }
+
+    /**
+     * The transitions out of a state, each with how many chars it consumes.
+     * An edge for an empty set of chars can never be taken, so is left out.
+     */
+    pub(super) fn edges(&self, id: StateId) -> Vec<(StateId, usize)> {
+        match &self.states[id] {
+            Start(Some(next)) | Epsilon(Some(next)) => vec![(*next, 0)],
+            Split(lhs, rhs) => lhs.iter().chain(rhs.iter()).map(|to| (*to, 0)).collect(),
+            Match(Char::Set(ranges), _) if ranges.is_empty() => vec![],
+            Match(_, Some(next)) => vec![(*next, 1)],
+            _ => vec![],
+        }
+    }
+
+    /**
+     * The states on some path from the start to the end.
+     */
+    pub(super) fn useful_states(&self) -> Vec<bool> {
+        let n = self.states.len();
+        let mut from: Vec<Vec<StateId>> = vec![vec![]; n];
+        let mut reached = vec![false; n];
+        let mut stack = vec![self.start];
+        reached[self.start] = true;
+        while let Some(id) = stack.pop() {
+            for (to, _) in self.edges(id) {
+                from[to].push(id);
+                if !reached[to] {
+                    reached[to] = true;
+                    stack.push(to);
+                }
+            }
+        }
+
+        let mut useful = vec![false; n];
+        let mut stack: Vec<StateId> = (0..n)
+            .filter(|id| reached[*id] && matches!(self.states[*id], End))
+            .collect();
+        for id in &stack {
+            useful[*id] = true;
+        }
+        while let Some(id) = stack.pop() {
+            for previous in &from[id] {
+                if !useful[*previous] {
+                    useful[*previous] = true;
+                    stack.push(*previous);
+                }
+            }
+        }
+        useful
+    }
+}
+
+#[cfg(test)]
+mod lengths_tests {
+    use super::*;
+
+    fn lengths(pattern: &str) -> (usize, Option<usize>) {
+        let nfa = NFA::from(pattern).unwrap();
+        (nfa.min_length(), nfa.max_length())
+    }
+
+    #[test]
+    fn bounded() {
+        assert_eq!(lengths("abc"), (3, Some(3)));
+        assert_eq!(lengths("ab|c"), (1, Some(2)));
+        assert_eq!(lengths("a(b|cd)e"), (3, Some(4)));
+        assert_eq!(lengths("x([]y)*"), (1, Some(1)));
+    }
+
+    #[test]
+    fn unbounded() {
+        assert_eq!(lengths("ab*"), (1, None));
+        assert_eq!(lengths("(ab)+c"), (3, None));
+        assert_eq!(lengths("~(a)"), (0, None));
+    }
+
+    #[test]
+    fn empty() {
+        assert!(NFA::from("a*").unwrap().matches_empty());
+        assert!(NFA::from("(a|b*)c*").unwrap().matches_empty());
+        assert!(!NFA::from("a*b").unwrap().matches_empty());
+        assert_eq!(lengths("a[]"), (usize::MAX, Some(0)));
+    }
+
+    #[test]
+    fn set_operators() {
+        assert_eq!(lengths("[a-z]+&..."), (3, Some(3)));
+        assert_eq!(lengths("(a|bb|ccc)&~(bb)"), (1, Some(3)));
+        assert_eq!(lengths("a*&~(a*)"), (usize::MAX, Some(0)));
+    }
+}
@@ -0,0 +1,123 @@
+use super::State::*;
+use super::{StateId, NFA};
+
+/*
+ * Finding where in a line a match is, rather than only whether there is
+ * one. The NFA is simulated on every start position at once: each state
+ * the simulation is in remembers the earliest start that reached it, so
+ * the leftmost match is found in a single pass over the input.
+ */
+
+impl NFA {
+    /**
+     * Whether a line is long enough and has the literal text to hold a
+     * match somewhere, which is cheap to check before searching it.
+     */
+    pub fn could_match(&self, line: &str) -> bool {
+        line.len() >= self.shortest && self.literals.could_match(line)
+    }
+
+    /**
+     * The leftmost match in chars that starts at or after from, as the
+     * range of char indices it covers. Of the matches starting there the
+     * longest is taken, the way egrep picks them.
+     */
+    pub fn find_at(&self, chars: &[char], from: usize) -> Option<(usize, usize)> {
+        if from > chars.len() || chars.len() - from < self.shortest {
+            return None;
+        }
+        let mut best: Option<(usize, usize)> = None;
+        // the states the simulation is in, each with the start of the match that reached it,
+        // kept in order of those starts
+        let mut threads: Vec<(StateId, usize)> = vec![];
+        let mut seen = vec![usize::MAX; self.states.len()];
+        for at in from..=chars.len() {
+            if best.is_none() {
+                // a match starting here ranks below every match already under way
+                self.add_thread(&mut threads, &mut seen, at, self.start, at);
+            }
+            if let Some((_, start)) = threads.iter().find(|(id, _)| matches!(self.states[*id], End)) {
+                if best.is_none_or(|(best_start, _)| *start <= best_start) {
+                    best = Some((*start, at));
+                }
+            }
+            if let Some((best_start, _)) = best {
+                threads.retain(|(_, start)| *start <= best_start);
+            }
+            if at == chars.len() || (best.is_some() && threads.is_empty()) {
+                break;
+            }
+            let mut next = vec![];
+            for (id, start) in threads {
+                if let Match(c, Some(to)) = &self.states[id] {
+                    if c.matches(chars[at]) {
+                        self.add_thread(&mut next, &mut seen, at + 1, *to, start);
+                    }
+                }
+            }
+            threads = next;
+        }
+        best
+    }
+
+    /**
+     * Add a state and everything it reaches by epsilon transitions to the
+     * threads at a position, unless an earlier start got there first.
+     */
+    fn add_thread(&self, threads: &mut Vec<(StateId, usize)>, seen: &mut [usize], at: usize, id: StateId, start: usize) {
+        let mut stack = vec![id];
+        while let Some(id) = stack.pop() {
+            if seen[id] == at {
+                continue;
+            }
+            seen[id] = at;
+            threads.push((id, start));
+            match &self.states[id] {
+                Start(next) | Epsilon(next) => stack.extend(next),
+                Split(lhs, rhs) => stack.extend(rhs.iter().chain(lhs.iter())),
+                Match(_, _) | End => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod search_tests {
+    use crate::nfa::NFA;
+
+    fn find(pattern: &str, input: &str) -> Option<(usize, usize)> {
+        let chars: Vec<char> = input.chars().collect();
+        NFA::from(pattern).unwrap().find_at(&chars, 0)
+    }
+
+    #[test]
+    fn leftmost_longest() {
+        assert_eq!(find("b+", "abbbc"), Some((1, 4)));
+        assert_eq!(find("a|ab", "xxab"), Some((2, 4)));
+        assert_eq!(find("(a|b)*c", "ababc"), Some((0, 5)));
+        assert_eq!(find("bc|abcd", "abcd"), Some((0, 4)));
+        assert_eq!(find("x", "abc"), None);
+    }
+
+    #[test]
+    fn empty_matches() {
+        assert_eq!(find("a*", "bab"), Some((0, 0)));
+        assert_eq!(find("a*", ""), Some((0, 0)));
+        assert_eq!(find("[]", ""), None);
+    }
+
+    #[test]
+    fn starting_later() {
+        let nfa = NFA::from("ab").unwrap();
+        let chars: Vec<char> = "ab ab".chars().collect();
+        assert_eq!(nfa.find_at(&chars, 1), Some((3, 5)));
+        assert_eq!(nfa.find_at(&chars, 4), None);
+    }
+
+    #[test]
+    fn set_operators() {
+        let nfa = NFA::from_tokens(crate::tokenizer::Tokenizer::extended("[a-z]+-(if)")).unwrap();
+        let chars: Vec<char> = "if x".chars().collect();
+        assert_eq!(nfa.find_at(&chars, 0), Some((0, 1)));
+    }
+}
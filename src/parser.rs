@@ -1,5 +1,11 @@
-use super::tokenizer::{Token, Tokenizer};
-use std::iter::Peekable;
+pub mod explain;
+pub mod helpers;
+pub mod literals;
+pub mod pattern;
+pub mod simplify;
+
+use super::tokenizer::{needs_escape, write_class, Span, Token, Tokenizer};
+use std::fmt;
 
 /**
  * thbc - Tar Heel Egrep - Parser
@@ -13,7 +19,7 @@ use std::iter::Peekable;
  */
 
 // elements in AST, things that can be an AST
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum AST {
     Alternation(Box<AST>, Box<AST>),
     Catenation(Box<AST>, Box<AST>),
@@ -21,6 +27,12 @@ pub enum AST {
     AnyChar,
     Char(char),
     OneOrMore(Box<AST>),
+    // sorted, non-overlapping, non-adjacent inclusive ranges; see build_class
+    Class(Vec<(char, char)>),
+    // strings matched by both sides, by the lhs but not the rhs, and by nothing inside
+    Intersection(Box<AST>, Box<AST>),
+    Difference(Box<AST>, Box<AST>),
+    Complement(Box<AST>),
 }
 
 // Helper factory functions for building AST
@@ -48,27 +60,618 @@ pub fn build_anychar() -> AST {
     AST::AnyChar
 }
 
+pub fn build_class(ranges: Vec<(char, char)>) -> AST {
+    AST::Class(normalize_ranges(ranges))
+}
+
+pub fn build_intersection(left: AST, right: AST) -> AST {
+    AST::Intersection(Box::new(left), Box::new(right))
+}
+
+pub fn build_difference(left: AST, right: AST) -> AST {
+    AST::Difference(Box::new(left), Box::new(right))
+}
+
+pub fn build_complement(ast: AST) -> AST {
+    AST::Complement(Box::new(ast))
+}
+
+/**
+ * Sorts ranges of chars and merges any that overlap or touch, so that two
+ * classes matching the same chars always compare equal.
+ */
+pub fn normalize_ranges(mut ranges: Vec<(char, char)>) -> Vec<(char, char)> {
+    ranges.sort();
+    let mut merged: Vec<(char, char)> = vec![];
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some(last) if start as u32 <= last.1 as u32 + 1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+/**
+ * How tightly each kind of AST node binds when written back out as a
+ * pattern, from loosest to tightest. Atoms bind as tightly as closures.
+ */
+#[derive(PartialEq, PartialOrd)]
+enum Precedence {
+    Alternation,
+    Intersection,
+    Catenation,
+    Complement,
+    Closure,
+}
+
+impl AST {
+    fn precedence(&self) -> Precedence {
+        match self {
+            AST::Alternation(_, _) => Precedence::Alternation,
+            AST::Intersection(_, _) | AST::Difference(_, _) => Precedence::Intersection,
+            AST::Catenation(_, _) => Precedence::Catenation,
+            AST::Complement(_) => Precedence::Complement,
+            _ => Precedence::Closure,
+        }
+    }
+
+    /**
+     * Write the node out, wrapped in parens if it binds more loosely than
+     * its position needs. What is left to write is kept on an explicit
+     * stack, latest first, so long or deeply nested patterns cannot
+     * overflow the call stack.
+     */
+    fn write_at(&self, f: &mut fmt::Formatter, needed: Precedence) -> fmt::Result {
+        // a node to write where it binds at least so tightly, or text around one
+        enum Work<'a> {
+            Node(&'a AST, Precedence),
+            Text(&'static str),
+        }
+
+        let mut work = vec![Work::Node(self, needed)];
+        while let Some(item) = work.pop() {
+            let (ast, needed) = match item {
+                Work::Text(text) => {
+                    write!(f, "{}", text)?;
+                    continue;
+                }
+                Work::Node(ast, needed) => (ast, needed),
+            };
+            if ast.precedence() < needed {
+                write!(f, "(")?;
+                work.push(Work::Text(")"));
+                work.push(Work::Node(ast, Precedence::Alternation));
+                continue;
+            }
+            // pushed in reverse, so that they come off the stack in the order written
+            match ast {
+                // both operators are right associative, so only a nested lhs needs parens
+                AST::Alternation(lhs, rhs) => {
+                    work.push(Work::Node(rhs, Precedence::Alternation));
+                    work.push(Work::Text("|"));
+                    work.push(Work::Node(lhs, Precedence::Intersection));
+                }
+                AST::Catenation(lhs, rhs) => {
+                    work.push(Work::Node(rhs, Precedence::Catenation));
+                    work.push(Work::Node(lhs, Precedence::Complement));
+                }
+                // intersection and difference are left associative, so only a nested rhs does
+                AST::Intersection(lhs, rhs) => {
+                    work.push(Work::Node(rhs, Precedence::Catenation));
+                    work.push(Work::Text("&"));
+                    work.push(Work::Node(lhs, Precedence::Intersection));
+                }
+                AST::Difference(lhs, rhs) => {
+                    work.push(Work::Node(rhs, Precedence::Catenation));
+                    work.push(Work::Text("-"));
+                    work.push(Work::Node(lhs, Precedence::Intersection));
+                }
+                AST::Complement(ast) => {
+                    write!(f, "~")?;
+                    work.push(Work::Node(ast, Precedence::Complement));
+                }
+                AST::Closure(ast) => {
+                    work.push(Work::Text("*"));
+                    work.push(Work::Node(ast, Precedence::Closure));
+                }
+                AST::OneOrMore(ast) => {
+                    work.push(Work::Text("+"));
+                    work.push(Work::Node(ast, Precedence::Closure));
+                }
+                AST::AnyChar => write!(f, ".")?,
+                AST::Char(c) if needs_escape(*c) => write!(f, "\\{}", c)?,
+                AST::Char(c) => write!(f, "{}", c)?,
+                AST::Class(ranges) => write_class(f, ranges)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/**
+ * An AST displays as the shortest pattern that parses back to it, with
+ * parentheses only where precedence requires them and operator chars escaped.
+ */
+impl fmt::Display for AST {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write_at(f, Precedence::Alternation)
+    }
+}
+
+/**
+ * A read-only traversal of an AST. `enter` is called on each node before any
+ * of its children are visited (pre-order) and `leave` after all of them have
+ * been (post-order), with children visited in the order `AST::children`
+ * lists them. Both default to doing nothing.
+ */
+pub trait Visitor {
+    fn enter(&mut self, _ast: &AST) {}
+    fn leave(&mut self, _ast: &AST) {}
+}
+
+/**
+ * A bottom-up rewrite of an AST. `fold` is handed each node after its
+ * children have already been folded, and returns what should replace it.
+ */
+pub trait Fold {
+    fn fold(&mut self, ast: AST) -> AST;
+}
+
+/**
+ * A node whose children have been taken out of it, so that they can be
+ * folded on their own and then put back with `rebuild`.
+ */
+enum Shell {
+    Alternation,
+    Catenation,
+    Closure,
+    OneOrMore,
+    Intersection,
+    Difference,
+    Complement,
+    Leaf(AST),
+}
+
+impl Shell {
+    fn rebuild(self, mut children: Vec<AST>) -> AST {
+        let mut next = || children.remove(0);
+        match self {
+            Shell::Alternation => build_alternation(next(), next()),
+            Shell::Catenation => build_catenation(next(), next()),
+            Shell::Closure => build_closure(next()),
+            Shell::OneOrMore => build_one_or_more(next()),
+            Shell::Intersection => build_intersection(next(), next()),
+            Shell::Difference => build_difference(next(), next()),
+            Shell::Complement => build_complement(next()),
+            Shell::Leaf(ast) => ast,
+        }
+    }
+}
+
+/**
+ * The default walkers. These are the only places, along with the parser
+ * and Display, that need to learn about a new kind of AST node; passes built
+ * on Visitor and Fold pick it up from here.
+ */
+impl AST {
+    /**
+     * The children of a node, in order.
+     */
+    pub fn children(&self) -> Vec<&AST> {
+        match self {
+            AST::Alternation(lhs, rhs)
+            | AST::Catenation(lhs, rhs)
+            | AST::Intersection(lhs, rhs)
+            | AST::Difference(lhs, rhs) => vec![lhs, rhs],
+            AST::Closure(ast) | AST::OneOrMore(ast) | AST::Complement(ast) => vec![ast],
+            AST::AnyChar | AST::Char(_) | AST::Class(_) => vec![],
+        }
+    }
+
+    // takes a node apart into its shell and its children
+    fn into_parts(self) -> (Shell, Vec<AST>) {
+        match self {
+            AST::Alternation(lhs, rhs) => (Shell::Alternation, vec![*lhs, *rhs]),
+            AST::Catenation(lhs, rhs) => (Shell::Catenation, vec![*lhs, *rhs]),
+            AST::Closure(ast) => (Shell::Closure, vec![*ast]),
+            AST::OneOrMore(ast) => (Shell::OneOrMore, vec![*ast]),
+            AST::Intersection(lhs, rhs) => (Shell::Intersection, vec![*lhs, *rhs]),
+            AST::Difference(lhs, rhs) => (Shell::Difference, vec![*lhs, *rhs]),
+            AST::Complement(ast) => (Shell::Complement, vec![*ast]),
+            leaf => (Shell::Leaf(leaf), vec![]),
+        }
+    }
+
+    /**
+     * Visit every node of the tree. An explicit stack is used rather than
+     * recursion, so deep trees are as safe to walk as shallow ones.
+     */
+    pub fn walk<V: Visitor>(&self, visitor: &mut V) {
+        let mut stack = vec![(self, false)];
+        while let Some((node, children_done)) = stack.pop() {
+            if children_done {
+                visitor.leave(node);
+                continue;
+            }
+            visitor.enter(node);
+            stack.push((node, true));
+            for child in node.children().into_iter().rev() {
+                stack.push((child, false));
+            }
+        }
+    }
+
+    /**
+     * Rewrite the tree bottom up, also without recursion.
+     */
+    pub fn fold<F: Fold>(self, folder: &mut F) -> AST {
+        // a node still to take apart, or a shell waiting on this many folded children
+        enum Work {
+            Visit(AST),
+            Rebuild(Shell, usize),
+        }
+
+        let mut work = vec![Work::Visit(self)];
+        let mut done: Vec<AST> = vec![];
+        while let Some(item) = work.pop() {
+            match item {
+                Work::Visit(node) => {
+                    let (shell, children) = node.into_parts();
+                    work.push(Work::Rebuild(shell, children.len()));
+                    work.extend(children.into_iter().rev().map(Work::Visit));
+                }
+                Work::Rebuild(shell, count) => {
+                    let children = done.split_off(done.len() - count);
+                    done.push(folder.fold(shell.rebuild(children)));
+                }
+            }
+        }
+        done.pop().unwrap()
+    }
+
+    /**
+     * Compute a value for every node from the values of its children, bottom
+     * up, and return the root's. This suits analyses such as whether a
+     * pattern can match the empty string, or how long its matches can be.
+     */
+    pub fn synthesize<T, F: FnMut(&AST, Vec<T>) -> T>(&self, f: F) -> T {
+        struct Synthesizer<T, F> {
+            f: F,
+            values: Vec<T>,
+        }
+
+        impl<T, F: FnMut(&AST, Vec<T>) -> T> Visitor for Synthesizer<T, F> {
+            fn leave(&mut self, ast: &AST) {
+                let at = self.values.len() - ast.children().len();
+                let children = self.values.split_off(at);
+                let value = (self.f)(ast, children);
+                self.values.push(value);
+            }
+        }
+
+        let mut synthesizer = Synthesizer { f, values: vec![] };
+        self.walk(&mut synthesizer);
+        synthesizer.values.pop().unwrap()
+    }
+}
+
+/**
+ * How many parenthesized groups may be open at once before parsing gives up
+ * with an error. Machine-generated patterns can nest arbitrarily deep, and
+ * every later pass over the AST has to be able to cope with the result.
+ */
+pub const DEFAULT_NEST_LIMIT: usize = 1000;
+
+/**
+ * The source spans of an AST, shaped like the AST itself: each node's
+ * children are listed in the same order the AST node holds them. A
+ * parenthesized node's span includes its parens.
+ */
+#[derive(Debug, PartialEq)]
+pub struct SpanTree {
+    pub span: Span,
+    pub children: Vec<SpanTree>,
+}
+
+/**
+ * A span tree is as deep as its AST, so it is taken apart with a stack of its
+ * own rather than by the compiler's recursive drop glue.
+ */
+impl Drop for SpanTree {
+    fn drop(&mut self) {
+        let mut stack = std::mem::take(&mut self.children);
+        while let Some(mut tree) = stack.pop() {
+            stack.append(&mut tree.children);
+        }
+    }
+}
+
+// an AST node paired with the spans of it and its children
+type Spanned = (AST, SpanTree);
+
+fn spanned_leaf(ast: AST, span: Span) -> Spanned {
+    (
+        ast,
+        SpanTree {
+            span,
+            children: vec![],
+        },
+    )
+}
+
+fn spanned_unary(build: fn(AST) -> AST, (ast, tree): Spanned, end: usize) -> Spanned {
+    let span = Span {
+        start: tree.span.start,
+        end,
+    };
+    (
+        build(ast),
+        SpanTree {
+            span,
+            children: vec![tree],
+        },
+    )
+}
+
+fn spanned_binary(build: fn(AST, AST) -> AST, lhs: Spanned, rhs: Spanned) -> Spanned {
+    let span = Span {
+        start: lhs.1.span.start,
+        end: rhs.1.span.end,
+    };
+    (
+        build(lhs.0, rhs.0),
+        SpanTree {
+            span,
+            children: vec![lhs.1, rhs.1],
+        },
+    )
+}
+
 pub struct Parser<'tokens> {
     // parser needs a tokenizer to process the elements of input
-    tokens: Peekable<Tokenizer<'tokens>>,
+    tokens: Tokenizer<'tokens>,
+    // maximum number of groups that may be open at the same time
+    nest_limit: usize,
 }
 
 impl<'tokens> Parser<'tokens> {
     pub fn parse(tokenizer: Tokenizer<'tokens>) -> Result<AST, String> {
+        Parser::parse_with_limit(tokenizer, DEFAULT_NEST_LIMIT)
+    }
+
+    /**
+     * Parse with a caller-chosen limit on how deeply groups may be nested.
+     */
+    pub fn parse_with_limit(tokenizer: Tokenizer<'tokens>, nest_limit: usize) -> Result<AST, String> {
         let mut parser = Parser {
-            // create a peekable tokenizer to make tokens to parse
-            tokens: tokenizer.peekable(),
+            tokens: tokenizer,
+            nest_limit,
+        };
+
+        //returns type Result<Expr,String> of parsed syntax stree or an error
+        Ok(parser.regexpr()?.0)
+    }
+
+    /**
+     * Parse, also returning where in the input each node of the AST came from.
+     */
+    pub fn parse_spanned(tokenizer: Tokenizer<'tokens>) -> Result<(AST, SpanTree), String> {
+        Parser::parse_spanned_with_limit(tokenizer, DEFAULT_NEST_LIMIT)
+    }
+
+    /**
+     * Parse with spans and a caller-chosen limit on how deeply groups may be
+     * nested.
+     */
+    pub fn parse_spanned_with_limit(
+        tokenizer: Tokenizer<'tokens>,
+        nest_limit: usize,
+    ) -> Result<(AST, SpanTree), String> {
+        let mut parser = Parser {
+            tokens: tokenizer,
+            nest_limit,
+        };
+        parser.regexpr()
+    }
+}
+
+// an operand of & or -, with the builder for the operator that follows it
+type Operand = (Spanned, fn(AST, AST) -> AST);
+
+/**
+ * One level of parenthesized nesting in the pattern being parsed. The
+ * alternatives are the finished branches to the left of each union bar, the
+ * operands are the finished catenations to the left of each & or - in the
+ * current branch, and the terms are the catenation currently being built.
+ */
+struct Group {
+    // offset of the lparen that opened the group
+    start: usize,
+    alternatives: Vec<Spanned>,
+    operands: Vec<Operand>,
+    terms: Vec<Spanned>,
+    // each ~ waiting on the term it applies to to be finished, as the index
+    // that term has in terms along with the offset of the ~
+    complements: Vec<(usize, usize)>,
+}
+
+impl Group {
+    fn new(start: usize) -> Group {
+        Group {
+            start,
+            alternatives: vec![],
+            operands: vec![],
+            terms: vec![],
+            complements: vec![],
+        }
+    }
+
+    // adds a term once the one before it can take no more postfix operators
+    fn push_term(&mut self, term: Spanned) {
+        self.settle();
+        self.terms.push(term);
+    }
+
+    // applies every ~ whose term is finished, innermost first
+    fn settle(&mut self) {
+        while let Some(&(index, start)) = self.complements.last() {
+            if index >= self.terms.len() {
+                break;
+            }
+            self.complements.pop();
+            let (ast, tree) = self.terms.pop().unwrap();
+            let end = tree.span.end;
+            let (ast, mut tree) = spanned_unary(build_complement, (ast, tree), end);
+            tree.span.start = start;
+            self.terms.push((ast, tree));
+        }
+    }
+
+    // takes the most recent term so an operator can be applied to it
+    fn last_term(&mut self) -> Result<Spanned, String> {
+        if self.complements.iter().any(|&(index, _)| index == self.terms.len()) {
+            return Err("unexpected input".to_string());
+        }
+        match self.terms.pop() {
+            Some(term) => Ok(term),
+            None => Err("unexpected input".to_string()),
+        }
+    }
+
+    // folds the pending terms into a right-leaning catenation, the same shape
+    // the grammar Cat -> Closure Cat produces
+    fn catenation(&mut self, empty_err: &str) -> Result<Spanned, String> {
+        self.settle();
+        if !self.complements.is_empty() {
+            return Err(String::from(empty_err));
+        }
+        let mut result = match self.terms.pop() {
+            Some(last) => last,
+            None => return Err(String::from(empty_err)),
         };
+        while let Some(term) = self.terms.pop() {
+            result = spanned_binary(build_catenation, term, result);
+        }
+        Ok(result)
+    }
 
-        //calls parser expression returns Result<Expr, String>
-        let res = parser.regexpr();
+    // finishes the current branch, folding its operands into a left-leaning
+    // run of intersections and differences
+    fn branch(&mut self, empty_err: &str) -> Result<Spanned, String> {
+        let last = self.catenation(empty_err)?;
+        let mut operands = self.operands.drain(..);
+        let (mut result, mut op) = match operands.next() {
+            Some(first) => first,
+            None => return Ok(last),
+        };
+        for (operand, next_op) in operands {
+            result = spanned_binary(op, result, operand);
+            op = next_op;
+        }
+        Ok(spanned_binary(op, result, last))
+    }
 
-        //If there are still tokens left in the parser, return an error
-        if let Some(c) = parser.tokens.peek() {
-            return Err(format!("Expected end of input, found {:?}", c));
-        } else {
-            //returns type Result<Expr,String> of parsed syntax stree or an error
-            return res;
+    // closes the group, folding every branch into a right-leaning alternation
+    fn finish(mut self, empty_err: &str) -> Result<Spanned, String> {
+        let mut result = self.branch(empty_err)?;
+        while let Some(branch) = self.alternatives.pop() {
+            result = spanned_binary(build_alternation, branch, result);
+        }
+        Ok(result)
+    }
+}
+
+/**
+ * Internal-only parser methods to process the grammar. Rather than recursing
+ * once per group and per catenated term, the parser keeps an explicit stack
+ * of open groups so that deeply nested patterns end in an error instead of a
+ * stack overflow.
+ */
+impl<'tokens> Parser<'tokens> {
+    // regexpr is our "base" function, i.e. it is the first place the input is mapped to
+    fn regexpr(&mut self) -> Result<Spanned, String> {
+        let mut open: Vec<Group> = vec![];
+        let mut group = Group::new(0);
+
+        loop {
+            match self.tokens.next_spanned() {
+                // AnyChar and Char are the atoms, nothing is smaller in our grammar
+                Some((Token::AnyChar, span)) => group.push_term(spanned_leaf(build_anychar(), span)),
+                Some((Token::Char(c), span)) => group.push_term(spanned_leaf(build_char(c), span)),
+                Some((Token::Class(ranges), span)) => {
+                    group.push_term(spanned_leaf(build_class(ranges), span))
+                }
+                // an lparen saves the enclosing group and starts a fresh one
+                Some((Token::LParen, span)) | Some((Token::NamedLParen(_), span)) => {
+                    if open.len() >= self.nest_limit {
+                        return Err(format!(
+                            "Groups nested more than {} deep",
+                            self.nest_limit
+                        ));
+                    }
+                    open.push(group);
+                    group = Group::new(span.start);
+                }
+                // an rparen closes the current group, which becomes a term of the enclosing one
+                Some((Token::RParen, span)) => {
+                    let mut outer = match open.pop() {
+                        Some(outer) => outer,
+                        None => {
+                            return Err(match group.finish("") {
+                                Ok((before, _)) => format!(
+                                    "Expected end of input after {}, found {}",
+                                    before,
+                                    Token::RParen
+                                ),
+                                Err(_) => format!("Expected end of input, found {}", Token::RParen),
+                            })
+                        }
+                    };
+                    let start = group.start;
+                    let (inner, mut tree) = group.finish("unexpected input")?;
+                    tree.span = Span {
+                        start,
+                        end: span.end,
+                    };
+                    outer.push_term((inner, tree));
+                    group = outer;
+                }
+                // kleene star and plus apply to the term just before them
+                Some((Token::KleeneStar, span)) => {
+                    let term = group.last_term()?;
+                    group.terms.push(spanned_unary(build_closure, term, span.end));
+                }
+                Some((Token::KleenePlus, span)) => {
+                    let term = group.last_term()?;
+                    group.terms.push(spanned_unary(build_one_or_more, term, span.end));
+                }
+                // a union bar ends the branch on its left
+                Some((Token::UnionBar, _)) => {
+                    let branch = group.branch("unexpected input")?;
+                    group.alternatives.push(branch);
+                }
+                // intersection and difference end the catenation on their left
+                Some((Token::Intersection, _)) => {
+                    let operand = group.catenation("unexpected input")?;
+                    group.operands.push((operand, build_intersection));
+                }
+                Some((Token::Difference, _)) => {
+                    let operand = group.catenation("unexpected input")?;
+                    group.operands.push((operand, build_difference));
+                }
+                // a complement applies to the next term, once any postfix operators are on it
+                Some((Token::Complement, span)) => {
+                    group.settle();
+                    group.complements.push((group.terms.len(), span.start));
+                }
+                None => {
+                    if !open.is_empty() {
+                        return Err(String::from("Unexpected end of input")); // unclosed parentheses case
+                    }
+                    return group.finish("Unexpected end of input");
+                }
+            }
         }
     }
 }
@@ -82,7 +685,6 @@ mod parsertests {
     mod basictests {
 
         use super::*;
-        use crate::parser::AST::*;
 
         #[test]
         fn parse_char() {
@@ -124,7 +726,6 @@ mod parsertests {
     mod intermediatetests {
 
         use super::*;
-        use crate::parser::*;
 
         #[test]
         fn parse1() {
@@ -165,154 +766,239 @@ mod parsertests {
              assert_eq!(Catenation(Box::new(OneOrMore(Box::new(Char('u')))),Box::new(OneOrMore(Box::new(Char('u'))))), res);
         }
 
-        
-    }
-    
+        #[test]
+        fn parse_stacked_closures() {
+            let res = Parser::parse(Tokenizer::new("a*+")).unwrap();
+            assert_eq!(OneOrMore(Box::new(Closure(Box::new(Char('a'))))), res);
+        }
 
-}
+        #[test]
+        fn parse_set_operators() {
+            // & binds more loosely than catenation and more tightly than |
+            let res = Parser::parse(Tokenizer::new("ab&c|d")).unwrap();
+            assert_eq!(
+                build_alternation(
+                    build_intersection(build_catenation(Char('a'), Char('b')), Char('c')),
+                    Char('d')
+                ),
+                res
+            );
+            // ~ takes the postfix operators on its term, and nothing after it
+            let res = Parser::parse(Tokenizer::new("~a*b")).unwrap();
+            assert_eq!(
+                build_catenation(build_complement(build_closure(Char('a'))), Char('b')),
+                res
+            );
+            // - is left associative, and only an operator in the extended syntax
+            let res = Parser::parse(Tokenizer::extended("a-b&~~c")).unwrap();
+            assert_eq!(
+                build_intersection(
+                    build_difference(Char('a'), Char('b')),
+                    build_complement(build_complement(Char('c')))
+                ),
+                res
+            );
+            let res = Parser::parse(Tokenizer::new("a-b")).unwrap();
+            assert_eq!(build_catenation(Char('a'), build_catenation(Char('-'), Char('b'))), res);
+        }
 
-/**
- * Internal-only parser methods to process the grammar via recursive descent.
- */
-impl<'tokens> Parser<'tokens> {
-    // regexpr is our "base" function, i.e. it is the first place the input is mapped to
-    fn regexpr(&mut self) -> Result<AST, String> {
-        let regex = self.maybe_regex()?;
-        Ok(regex)
+        #[test]
+        fn set_operator_spans() {
+            let (_, spans) = Parser::parse_spanned(Tokenizer::new("x&~(ab)")).unwrap();
+            assert_eq!(spans.span, Span { start: 0, end: 7 });
+            assert_eq!(spans.children[1].span, Span { start: 2, end: 7 });
+            assert_eq!(spans.children[1].children[0].span, Span { start: 3, end: 7 });
+        }
     }
-    //Atom -> lparen RegExpr rparen | AnyChar | Char  according to grammar
-    fn atom(&mut self) -> Result<AST, String> {
-        // atom is sent input by closure, so this is our "base case" of recursion, i.e. nothing is
-        // smaller than an atom in our grammar
-        //Take next token if there is one (and doesn't throw error)
-        let t: Token = self.take_next_token()?;
-        match t {
-            //if the token is anychar, make a new AST and return
-            Token::AnyChar => {
-                return Ok(build_anychar());
+
+    mod displaytests {
+
+        use super::*;
+        use rand::prelude::*;
+
+        fn round_trip(pattern: &str) -> String {
+            Parser::parse(Tokenizer::new(pattern)).unwrap().to_string()
+        }
+
+        #[test]
+        fn minimal_parens() {
+            assert_eq!(round_trip("a|b|c"), "a|b|c");
+            assert_eq!(round_trip("(a|b)|c"), "(a|b)|c");
+            assert_eq!(round_trip("((ab))c"), "(ab)c");
+            assert_eq!(round_trip("(ab)*(c)+"), "(ab)*c+");
+            assert_eq!(round_trip("(a*)*"), "a**");
+            assert_eq!(round_trip("(a|b)(c|d)"), "(a|b)(c|d)");
+            assert_eq!(round_trip("(a&b)&(c|d)"), "a&b&(c|d)");
+            assert_eq!(round_trip("a&(b&c)"), "a&(b&c)");
+            assert_eq!(round_trip("~(a*)(~b)*"), "~a*(~b)*");
+        }
+
+        #[test]
+        fn long_patterns() {
+            let literal = "ab".repeat(10_000);
+            assert_eq!(round_trip(&literal), literal);
+            let nested = format!("{}a{}", "(~".repeat(900), ")".repeat(900));
+            assert_eq!(round_trip(&nested), "~".repeat(900) + "a");
+            let unopened = Parser::parse(Tokenizer::new(&(literal.clone() + ")"))).unwrap_err();
+            assert!(unopened.starts_with("Expected end of input after abab"));
+        }
+
+        #[test]
+        fn escapes_operators() {
+            assert_eq!(round_trip("\\.\\|\\ x"), "\\.\\|\\ x");
+            assert_eq!(build_char('('), Parser::parse(Tokenizer::new(&build_char('(').to_string())).unwrap());
+        }
+
+        // builds a random AST no deeper than depth, drawing chars that need escaping too
+        fn random_ast(rng: &mut ThreadRng, depth: u32) -> AST {
+            let chars = ['a', 'b', '|', '*', '+', '.', '(', ')', '\\', ' ', '&', '~', '-'];
+            let class_chars = ['a', 'b', 'c', ']', '-', '\\', '^', ' '];
+            let choice = if depth == 0 { rng.gen_range(0, 3) } else { rng.gen_range(0, 10) };
+            match choice {
+                0 => build_anychar(),
+                1 => build_char(*chars.choose(rng).unwrap()),
+                2 => build_class(
+                    (0..rng.gen_range(0, 4))
+                        .map(|_| {
+                            let (a, b) = (*class_chars.choose(rng).unwrap(), *class_chars.choose(rng).unwrap());
+                            (a.min(b), a.max(b))
+                        })
+                        .collect(),
+                ),
+                3 => build_alternation(random_ast(rng, depth - 1), random_ast(rng, depth - 1)),
+                4 => build_catenation(random_ast(rng, depth - 1), random_ast(rng, depth - 1)),
+                5 => build_closure(random_ast(rng, depth - 1)),
+                6 => build_intersection(random_ast(rng, depth - 1), random_ast(rng, depth - 1)),
+                7 => build_difference(random_ast(rng, depth - 1), random_ast(rng, depth - 1)),
+                8 => build_complement(random_ast(rng, depth - 1)),
+                _ => build_one_or_more(random_ast(rng, depth - 1)),
             }
-            //If token is an LParen, input should follow lparen AST RParen
-            //Consume tokens in this order and return the AST
-            Token::LParen => {
-                // x is next ast or error
-                let x = self.regexpr()?;
-                //r should be rparen
-                let r = self.consume_token(Token::RParen);
-                if !r.is_ok() {
-                    return Err(String::from("Unexpected end of input")); // unclosed parentheses case
-                }
-                // otherwise return x
-                return Ok(x);
+        }
+
+        #[test]
+        fn random_round_trips() {
+            let mut rng = rand::thread_rng();
+            for _ in 0..1000 {
+                let ast = random_ast(&mut rng, 6);
+                let printed = ast.to_string();
+                assert_eq!(Parser::parse(Tokenizer::extended(&printed)), Ok(ast), "{}", printed);
             }
-            // token character should just return Ok(c)
-            Token::Char(c) => {
-                return Ok(build_char(c));
+        }
+    }
+
+    mod traversaltests {
+
+        use super::*;
+
+        // records the order nodes are entered and left in
+        struct Recorder(Vec<String>);
+
+        impl Visitor for Recorder {
+            fn enter(&mut self, ast: &AST) {
+                self.0.push(format!("+{}", ast));
             }
-            _ => {
-                return Err("unexpected input".to_string());
+            fn leave(&mut self, ast: &AST) {
+                self.0.push(format!("-{}", ast));
             }
         }
-    }
 
-    fn closure(&mut self) -> Result<AST, String> {
-        // closure receives input from cat()
-        let first_term = self.atom()?;
-    
-        //checks for kleene star
-        if self.peek_kleene_star().is_some() {
-            let kleene_star = self.take_next_token();
-            return Ok(build_closure(first_term)); // if there's a kleene star, make a closure
+        #[test]
+        fn walk_order() {
+            let ast = Parser::parse(Tokenizer::new("a*|b")).unwrap();
+            let mut recorder = Recorder(vec![]);
+            ast.walk(&mut recorder);
+            assert_eq!(recorder.0, vec!["+a*|b", "+a*", "+a", "-a", "-a*", "+b", "-b", "-a*|b"]);
         }
 
-        //checks for kleen plus and will take next token
-        if self.peek_kleene_plus().is_some() {
-            let kleene_plus = self.take_next_token();
-            return Ok(build_one_or_more(first_term));
+        // swaps every a for a b
+        struct Swap;
+
+        impl Fold for Swap {
+            fn fold(&mut self, ast: AST) -> AST {
+                match ast {
+                    Char('a') => Char('b'),
+                    ast => ast,
+                }
+            }
         }
 
-        Ok(first_term) // if no kleene star, just return first term wrapped in result
-    }
+        #[test]
+        fn fold_rewrites_everywhere() {
+            let ast = Parser::parse(Tokenizer::new("(a|c)a+")).unwrap();
+            assert_eq!(ast.fold(&mut Swap).to_string(), "(b|c)b+");
+        }
 
-    fn cat(&mut self) -> Result<AST, String> {
+        #[test]
+        fn synthesize_counts() {
+            let ast = Parser::parse(Tokenizer::new("ab(c|d)*")).unwrap();
+            let chars = ast.synthesize(|node, children: Vec<usize>| match node {
+                Char(_) => 1,
+                _ => children.iter().sum(),
+            });
+            assert_eq!(chars, 4);
+        }
 
-        // this is somewhat the third stage of parsing, because maybe_regex maps here
-        let first_term = self.closure()?; // see if first term is a closure AST
-        let second_term = self.maybe_cat(); // check and see if second term is an AST also
-        if second_term.is_ok() {
-            return Ok(build_catenation(first_term, second_term.unwrap())); // catenate first and second terms
+        #[test]
+        fn long_trees() {
+            let ast = Parser::parse(Tokenizer::new(&"a".repeat(5_000))).unwrap();
+            assert_eq!(ast.synthesize(|_, children: Vec<u32>| 1 + children.iter().sum::<u32>()), 9_999);
+            assert_eq!(ast.fold(&mut Swap), Parser::parse(Tokenizer::new(&"b".repeat(5_000))).unwrap());
         }
-        return Ok(first_term); // if there is no second term don't make a new catenation, just return the first term wrapped in result
     }
 
-    fn maybe_cat(&mut self) -> Result<AST, String> {
-        match self.tokens.peek() {
-            // this match statement sends to cat if there is one, otherwise throws an error
-            Some(Token::LParen) | Some(Token::AnyChar) => self.cat(),
-            Some(Token::Char(c)) => self.cat(),
-            _ => Err(String::from("this is an error we are checking")),
+    mod errortests {
+
+        use super::*;
+
+        #[test]
+        fn unclosed_group() {
+            assert!(Parser::parse(Tokenizer::new("(ab")).is_err());
         }
-    }
 
-    fn maybe_regex(&mut self) -> Result<AST, String> {
-        // this is the second stage of recursive parsing, because regexpr maps directly here
-        let lhs = self.cat()?; // base term is a catenation
-        if self.peek_union_bar().is_some() {
-            // test for alternation
-            let union_bar = self.take_next_token();
-            let rhs = self.regexpr()?;
-            return Ok(build_alternation(lhs, rhs)); // return an alternation wrapped in result
+        #[test]
+        fn unopened_group() {
+            assert_eq!(
+                Parser::parse(Tokenizer::new("a|b)")),
+                Err(String::from("Expected end of input after a|b, found )"))
+            );
         }
-        return Ok(lhs); // if there isn't a union bar just return lhs
-    }
-}
-impl<'tokens> Parser<'tokens> {
-    // take next token consumes the token and returns it wrapped in a result
-    fn take_next_token(&mut self) -> Result<Token, String> {
-        if let Some(token) = self.tokens.next() {
-            Ok(token)
-        } else {
-            Err(String::from("Unexpected end of input"))
+
+        #[test]
+        fn empty_branch() {
+            assert!(Parser::parse(Tokenizer::new("a|")).is_err());
+            assert!(Parser::parse(Tokenizer::new("|a")).is_err());
+            assert!(Parser::parse(Tokenizer::new("()")).is_err());
         }
-    }
 
-    // consumes a token and returns it wrapped in a result, but this is for when you know which
-    // token you expect
-    fn consume_token(&mut self, expected: Token) -> Result<Token, String> {
-        if let Some(next) = self.tokens.next() {
-            if next != expected {
-                Err(format!("Expected: {:?} - Found {:?}", expected, next))
-            } else {
-                Ok(next)
-            }
-        } else {
-            Err(String::from("Unexpected end of input"))
+        #[test]
+        fn dangling_closure() {
+            assert!(Parser::parse(Tokenizer::new("*a")).is_err());
+            assert!(Parser::parse(Tokenizer::new("a|+")).is_err());
         }
-    }
 
-    // function to peek if the next char is a kleene star
-    fn peek_kleene_star(&mut self) -> Option<char> {
-        if let Some(Token::KleeneStar) = self.tokens.peek() {
-            Some('*')
-        } else {
-            None
+        #[test]
+        fn dangling_set_operators() {
+            assert!(Parser::parse(Tokenizer::new("a&")).is_err());
+            assert!(Parser::parse(Tokenizer::new("&a")).is_err());
+            assert!(Parser::parse(Tokenizer::new("a&|b")).is_err());
+            assert!(Parser::parse(Tokenizer::new("a~")).is_err());
+            assert!(Parser::parse(Tokenizer::new("(~)a")).is_err());
+            assert!(Parser::parse(Tokenizer::new("~*a")).is_err());
+            assert!(Parser::parse(Tokenizer::extended("a-")).is_err());
         }
-    }
 
-    //function to peek if the next char is a kleene plus
-    fn peek_kleene_plus(&mut self) -> Option<char> {
-        if let Some(Token::KleenePlus) = self.tokens.peek() {
-            Some('+')
-        } else {
-            None
+        #[test]
+        fn deep_nesting_within_limit() {
+            let pattern = format!("{}a{}", "(".repeat(500), ")*".repeat(500));
+            assert!(Parser::parse(Tokenizer::new(&pattern)).is_ok());
         }
-    }
 
-    // function to peek if the next char is a union bar
-    fn peek_union_bar(&mut self) -> Option<char> {
-        if let Some(Token::UnionBar) = self.tokens.peek() {
-            Some('|')
-        } else {
-            None
+        #[test]
+        fn deep_nesting_past_limit() {
+            let pattern = format!("{}a{}", "(".repeat(100_000), ")".repeat(100_000));
+            assert!(Parser::parse(Tokenizer::new(&pattern)).is_err());
+            assert!(Parser::parse_with_limit(Tokenizer::new("((a))"), 1).is_err());
+            assert!(Parser::parse_with_limit(Tokenizer::new("(a)"), 1).is_ok());
         }
     }
 }
@@ -0,0 +1,202 @@
+use super::AST;
+
+/*
+ * Plain-English descriptions of patterns, for thegrep --explain.
+ */
+
+/**
+ * What an AST node explains to, before it is laid out as indented lines.
+ * Runs of catenations and alternations are gathered into one Sequence or
+ * Choice, and runs of literal chars into one Text.
+ */
+enum Part {
+    Line(String),
+    Text(String),
+    Sequence(Vec<Part>),
+    Choice(Vec<Part>),
+    Labeled(&'static str, Box<Part>),
+    Both(Box<Part>, &'static str, Box<Part>),
+}
+
+/**
+ * Explain a pattern as indented lines of English, two spaces per level.
+ */
+pub fn explain(ast: &AST) -> String {
+    let part = ast.synthesize(|node, mut children: Vec<Part>| match node {
+        AST::AnyChar => Part::Line(String::from("any character")),
+        AST::Char(c) => Part::Text(c.to_string()),
+        AST::Class(ranges) => {
+            let ranges: Vec<String> = ranges
+                .iter()
+                .map(|(start, end)| {
+                    if start == end {
+                        format!("{:?}", start)
+                    } else {
+                        format!("{:?} to {:?}", start, end)
+                    }
+                })
+                .collect();
+            if ranges.is_empty() {
+                Part::Line(String::from("nothing (an empty class never matches)"))
+            } else {
+                Part::Line(format!("one character from: {}", ranges.join(", ")))
+            }
+        }
+        AST::Closure(_) => Part::Labeled("zero or more of", Box::new(children.pop().unwrap())),
+        AST::OneOrMore(_) => Part::Labeled("one or more of", Box::new(children.pop().unwrap())),
+        AST::Catenation(_, _) => {
+            let rhs = children.pop().unwrap();
+            let lhs = children.pop().unwrap();
+            let mut parts = match rhs {
+                Part::Sequence(parts) => parts,
+                rhs => vec![rhs],
+            };
+            match (lhs, parts.first_mut()) {
+                (Part::Text(before), Some(Part::Text(after))) => after.insert_str(0, &before),
+                (lhs, _) => parts.insert(0, lhs),
+            }
+            if parts.len() == 1 {
+                parts.pop().unwrap()
+            } else {
+                Part::Sequence(parts)
+            }
+        }
+        AST::Intersection(_, _) | AST::Difference(_, _) => {
+            let rhs = children.pop().unwrap();
+            let lhs = children.pop().unwrap();
+            let connective = match node {
+                AST::Intersection(_, _) => "and also:",
+                _ => "but not:",
+            };
+            Part::Both(Box::new(lhs), connective, Box::new(rhs))
+        }
+        AST::Complement(_) => Part::Labeled("anything except", Box::new(children.pop().unwrap())),
+        AST::Alternation(_, _) => {
+            let rhs = children.pop().unwrap();
+            let lhs = children.pop().unwrap();
+            let mut parts = match rhs {
+                Part::Choice(parts) => parts,
+                rhs => vec![rhs],
+            };
+            parts.insert(0, lhs);
+            Part::Choice(parts)
+        }
+    });
+
+    let mut lines = vec![];
+    layout(&part, 0, &mut lines);
+    lines.join("\n")
+}
+
+/**
+ * A single char as it reads in a sentence.
+ */
+fn describe_char(c: char) -> String {
+    match c {
+        ' ' => String::from("a space"),
+        '\t' => String::from("a tab"),
+        c if c.is_alphabetic() => format!("the letter {:?}", c),
+        c if c.is_numeric() => format!("the digit {:?}", c),
+        c => format!("the character {:?}", c),
+    }
+}
+
+/**
+ * The one-line description of a part, if it has one.
+ */
+fn one_line(part: &Part) -> Option<String> {
+    match part {
+        Part::Line(line) => Some(line.clone()),
+        Part::Text(text) if text.chars().count() == 1 => {
+            Some(describe_char(text.chars().next().unwrap()))
+        }
+        Part::Text(text) => Some(format!("the text {:?}", text)),
+        Part::Labeled(what, part) => one_line(part).map(|line| format!("{}: {}", what, line)),
+        Part::Sequence(_) | Part::Choice(_) | Part::Both(_, _, _) => None,
+    }
+}
+
+fn layout(part: &Part, depth: usize, lines: &mut Vec<String>) {
+    let indent = "  ".repeat(depth);
+    if let Some(line) = one_line(part) {
+        lines.push(format!("{}{}", indent, line));
+        return;
+    }
+    match part {
+        Part::Sequence(parts) => {
+            lines.push(format!("{}in sequence:", indent));
+            for part in parts {
+                layout(part, depth + 1, lines);
+            }
+        }
+        Part::Choice(parts) => {
+            for (i, part) in parts.iter().enumerate() {
+                lines.push(format!("{}{}", indent, if i == 0 { "either:" } else { "or:" }));
+                layout(part, depth + 1, lines);
+            }
+        }
+        Part::Labeled(what, part) => {
+            lines.push(format!("{}{}:", indent, what));
+            layout(part, depth + 1, lines);
+        }
+        Part::Both(lhs, connective, rhs) => {
+            lines.push(format!("{}strings matching:", indent));
+            layout(lhs, depth + 1, lines);
+            lines.push(format!("{}{}", indent, connective));
+            layout(rhs, depth + 1, lines);
+        }
+        Part::Line(_) | Part::Text(_) => {}
+    }
+}
+
+#[cfg(test)]
+mod explain_tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::tokenizer::Tokenizer;
+
+    fn explained(pattern: &str) -> String {
+        explain(&Parser::parse(Tokenizer::new(pattern)).unwrap())
+    }
+
+    #[test]
+    fn single_lines() {
+        assert_eq!(explained("u+"), "one or more of: the letter 'u'");
+        assert_eq!(explained(".*"), "zero or more of: any character");
+        assert_eq!(explained("\\*"), "the character '*'");
+        assert_eq!(explained("error"), "the text \"error\"");
+        assert_eq!(explained("[a-z_]"), "one character from: '_', 'a' to 'z'");
+    }
+
+    #[test]
+    fn sequences() {
+        assert_eq!(
+            explained("ERROR.*timeout"),
+            "in sequence:\n  the text \"ERROR\"\n  zero or more of: any character\n  the text \"timeout\""
+        );
+    }
+
+    #[test]
+    fn choices() {
+        assert_eq!(
+            explained("(cat|dog)s*"),
+            "in sequence:\n  either:\n    the text \"cat\"\n  or:\n    the text \"dog\"\n  zero or more of: the letter 's'"
+        );
+        assert_eq!(
+            explained("(a|1b)+"),
+            "one or more of:\n  either:\n    the letter 'a'\n  or:\n    the text \"1b\""
+        );
+    }
+
+    #[test]
+    fn set_operators() {
+        assert_eq!(
+            explained("[a-z]+&~(if|else)"),
+            "strings matching:\n  one or more of: one character from: 'a' to 'z'\nand also:\n  anything except:\n    either:\n      the text \"if\"\n    or:\n      the text \"else\""
+        );
+        assert_eq!(
+            explain(&Parser::parse(Tokenizer::extended("a.*-ab")).unwrap()),
+            "strings matching:\n  in sequence:\n    the letter 'a'\n    zero or more of: any character\nbut not:\n  the text \"ab\""
+        );
+    }
+}
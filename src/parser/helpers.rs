@@ -0,0 +1,263 @@
+use super::SpanTree;
+use super::AST;
+
+/*
+ * Helper functions for printing parse trees in formats that are easier to
+ * read and diff than the one-line Debug form, or easy for other tools to load.
+ */
+
+/**
+ * The name of a node's variant.
+ */
+fn name(ast: &AST) -> &'static str {
+    match ast {
+        AST::Alternation(_, _) => "Alternation",
+        AST::Catenation(_, _) => "Catenation",
+        AST::Closure(_) => "Closure",
+        AST::OneOrMore(_) => "OneOrMore",
+        AST::AnyChar => "AnyChar",
+        AST::Char(_) => "Char",
+        AST::Class(_) => "Class",
+        AST::Intersection(_, _) => "Intersection",
+        AST::Difference(_, _) => "Difference",
+        AST::Complement(_) => "Complement",
+    }
+}
+
+/**
+ * Label for a node in the human-readable formats.
+ */
+fn label(ast: &AST) -> String {
+    match ast {
+        AST::Char(c) => format!("Char {:?}", c),
+        AST::Class(_) => format!("Class {}", ast),
+        _ => String::from(name(ast)),
+    }
+}
+
+/**
+ * The children to show under a node in the human-readable formats. A run of
+ * alternations or catenations leaning right, as the parser builds them, is
+ * shown as one node with every operand as a child rather than a deep ladder.
+ */
+fn flat_children(ast: &AST) -> Vec<&AST> {
+    let mut children = vec![];
+    let mut node = ast;
+    loop {
+        match (ast, node) {
+            (AST::Alternation(_, _), AST::Alternation(lhs, rhs))
+            | (AST::Catenation(_, _), AST::Catenation(lhs, rhs)) => {
+                children.push(&**lhs);
+                node = rhs;
+            }
+            _ if std::ptr::eq(ast, node) => return ast.children(),
+            _ => {
+                children.push(node);
+                return children;
+            }
+        }
+    }
+}
+
+/**
+ * Generate an indented tree drawn with box-drawing characters.
+ */
+pub fn ast_tree(ast: &AST) -> String {
+    let mut tree = label(ast);
+    tree.push('\n');
+    write_tree_children(ast, "", &mut tree);
+    tree
+}
+
+fn write_tree_children(ast: &AST, prefix: &str, tree: &mut String) {
+    let children = flat_children(ast);
+    for (i, child) in children.iter().enumerate() {
+        let last = i + 1 == children.len();
+        tree.push_str(prefix);
+        tree.push_str(if last { "└── " } else { "├── " });
+        tree.push_str(&label(child));
+        tree.push('\n');
+        let child_prefix = format!("{}{}", prefix, if last { "    " } else { "│   " });
+        write_tree_children(child, &child_prefix, tree);
+    }
+}
+
+/**
+ * Generate a one-line S-expression.
+ */
+pub fn ast_sexpr(ast: &AST) -> String {
+    let children = flat_children(ast);
+    let mut sexpr = format!("({}", name(ast));
+    match ast {
+        AST::Char(c) => sexpr.push_str(&format!(" {:?}", c)),
+        AST::Class(_) => sexpr.push_str(&format!(" {}", json_string(&ast.to_string()))),
+        _ if children.is_empty() => return label(ast),
+        _ => {}
+    }
+    for child in children {
+        sexpr.push(' ');
+        sexpr.push_str(&ast_sexpr(child));
+    }
+    sexpr.push(')');
+    sexpr
+}
+
+/**
+ * The children to show under a node in JSON, each with its spans, with runs
+ * of alternations or catenations flattened the way flat_children does.
+ */
+fn flat_spanned<'a>(ast: &'a AST, spans: &'a SpanTree) -> Vec<(&'a AST, &'a SpanTree)> {
+    let mut children = vec![];
+    let (mut node, mut node_spans) = (ast, spans);
+    loop {
+        match (ast, node) {
+            (AST::Alternation(_, _), AST::Alternation(lhs, rhs))
+            | (AST::Catenation(_, _), AST::Catenation(lhs, rhs)) => {
+                children.push((&**lhs, &node_spans.children[0]));
+                node = rhs;
+                node_spans = &node_spans.children[1];
+            }
+            _ if std::ptr::eq(ast, node) => return ast.children().into_iter().zip(&spans.children).collect(),
+            _ => {
+                children.push((node, node_spans));
+                return children;
+            }
+        }
+    }
+}
+
+/**
+ * Generate indented JSON, with the byte span of the pattern each node was
+ * parsed from. Runs of alternations or catenations are flat arrays of
+ * children, as in the other formats.
+ */
+pub fn ast_json(ast: &AST, spans: &SpanTree) -> String {
+    let mut json = String::new();
+    write_json(ast, spans, &mut json);
+    json
+}
+
+fn write_json(ast: &AST, spans: &SpanTree, json: &mut String) {
+    // a node to write at some depth, or the text that goes between or after nodes
+    enum Work<'a> {
+        Node(&'a AST, &'a SpanTree, usize),
+        Text(String),
+    }
+
+    let mut work = vec![Work::Node(ast, spans, 0)];
+    while let Some(item) = work.pop() {
+        let (ast, spans, depth) = match item {
+            Work::Text(text) => {
+                json.push_str(&text);
+                continue;
+            }
+            Work::Node(ast, spans, depth) => (ast, spans, depth),
+        };
+        let indent = "  ".repeat(depth);
+        let inner = format!("{}  ", indent);
+        json.push_str("{\n");
+        json.push_str(&format!("{}\"type\": \"{}\",\n", inner, name(ast)));
+        match ast {
+            AST::Char(c) => {
+                json.push_str(&format!("{}\"value\": {},\n", inner, json_string(&c.to_string())))
+            }
+            AST::Class(_) => {
+                json.push_str(&format!("{}\"value\": {},\n", inner, json_string(&ast.to_string())))
+            }
+            _ => {}
+        }
+        json.push_str(&format!(
+            "{}\"span\": [{}, {}]",
+            inner, spans.span.start, spans.span.end
+        ));
+        let children = flat_spanned(ast, spans);
+        if children.is_empty() {
+            json.push_str(&format!("\n{}}}", indent));
+            continue;
+        }
+        json.push_str(&format!(",\n{}\"children\": [\n", inner));
+        // pushed in reverse, so that they come off the stack in the order written
+        work.push(Work::Text(format!("\n{}]\n{}}}", inner, indent)));
+        let child_indent = format!("{}  ", inner);
+        for (i, (child, child_spans)) in children.into_iter().enumerate().rev() {
+            work.push(Work::Node(child, child_spans, depth + 2));
+            if i > 0 {
+                work.push(Work::Text(format!(",\n{}", child_indent)));
+            }
+        }
+        json.push_str(&child_indent);
+    }
+}
+
+/**
+ * Text as a quoted JSON string.
+ */
+fn json_string(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod formats {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::tokenizer::Tokenizer;
+
+    #[test]
+    fn tree() {
+        let ast = Parser::parse(Tokenizer::new("ab(c|d)*")).unwrap();
+        assert_eq!(
+            ast_tree(&ast),
+            "Catenation\n\
+             ├── Char 'a'\n\
+             ├── Char 'b'\n\
+             └── Closure\n    \
+                 └── Alternation\n        \
+                     ├── Char 'c'\n        \
+                     └── Char 'd'\n"
+        );
+    }
+
+    #[test]
+    fn sexpr() {
+        let ast = Parser::parse(Tokenizer::new("(ab)c|.+")).unwrap();
+        assert_eq!(
+            ast_sexpr(&ast),
+            "(Alternation (Catenation (Catenation (Char 'a') (Char 'b')) (Char 'c')) (OneOrMore AnyChar))"
+        );
+    }
+
+    #[test]
+    fn json() {
+        let (ast, spans) = Parser::parse_spanned(Tokenizer::new("(\\\")*")).unwrap();
+        assert_eq!(
+            ast_json(&ast, &spans),
+            "{\n  \"type\": \"Closure\",\n  \"span\": [0, 5],\n  \"children\": [\n    {\n      \
+             \"type\": \"Char\",\n      \"value\": \"\\\"\",\n      \"span\": [0, 4]\n    }\n  ]\n}"
+        );
+    }
+
+    #[test]
+    fn json_runs() {
+        let (ast, spans) = Parser::parse_spanned(Tokenizer::new("ab.|c")).unwrap();
+        let json = ast_json(&ast, &spans);
+        assert_eq!(json.matches("\"children\"").count(), 2);
+        assert!(json.starts_with("{\n  \"type\": \"Alternation\",\n  \"span\": [0, 5],\n  \"children\": [\n    {\n      \"type\": \"Catenation\",\n      \"span\": [0, 3],\n      \"children\": [\n        {\n          \"type\": \"Char\",\n          \"value\": \"a\",\n          \"span\": [0, 1]\n        },\n        {\n"));
+        assert!(json.ends_with("\"type\": \"AnyChar\",\n          \"span\": [2, 3]\n        }\n      ]\n    },\n    {\n      \"type\": \"Char\",\n      \"value\": \"c\",\n      \"span\": [4, 5]\n    }\n  ]\n}"));
+
+        let literal = "ab".repeat(10_000);
+        let (ast, spans) = Parser::parse_spanned(Tokenizer::new(&literal)).unwrap();
+        assert_eq!(ast_json(&ast, &spans).matches("\"type\": \"Char\"").count(), 20_000);
+    }
+}
@@ -0,0 +1,311 @@
+use super::AST;
+
+/*
+ * Literal text that every match of a pattern must contain. Finding a
+ * substring is far cheaper than running the NFA, so lines missing any of
+ * it can be thrown out before the NFA ever sees them.
+ */
+
+/**
+ * What every match of a pattern is known to contain: text it starts with,
+ * text it ends with, and other text somewhere in between. Any of them may
+ * be empty when nothing is known.
+ */
+#[derive(Debug, Default, PartialEq)]
+pub struct Literals {
+    pub prefix: String,
+    pub suffix: String,
+    pub inner: Vec<String>,
+}
+
+impl Literals {
+    /**
+     * Whether a line contains all of the required text, and so might hold a
+     * match. A line that does not certainly holds none.
+     */
+    pub fn could_match(&self, line: &str) -> bool {
+        line.contains(&self.prefix)
+            && line.contains(&self.suffix)
+            && self.inner.iter().all(|literal| line.contains(literal.as_str()))
+    }
+}
+
+/**
+ * What is known about the text a single node matches.
+ */
+struct Facts {
+    // the only string the node matches, if there is just one
+    exact: Option<String>,
+    prefix: String,
+    suffix: String,
+    required: Vec<String>,
+}
+
+impl Facts {
+    fn unknown() -> Facts {
+        Facts {
+            exact: None,
+            prefix: String::new(),
+            suffix: String::new(),
+            required: vec![],
+        }
+    }
+
+    fn exact(text: String) -> Facts {
+        Facts {
+            exact: Some(text.clone()),
+            prefix: text.clone(),
+            suffix: text.clone(),
+            required: vec![text],
+        }
+    }
+}
+
+/**
+ * Work out the literal text every match of a pattern must contain.
+ */
+pub fn required_literals(ast: &AST) -> Literals {
+    // a node still to look at, a run of catenated terms waiting on this many
+    // facts, or a node waiting on the facts about its children
+    enum Work<'a> {
+        Visit(&'a AST),
+        Catenation(usize),
+        Combine(&'a AST),
+    }
+
+    let mut work = vec![Work::Visit(ast)];
+    let mut done: Vec<Facts> = vec![];
+    while let Some(item) = work.pop() {
+        match item {
+            // a whole run is handled at once, since joining its text a node at
+            // a time would copy a long literal once for every char in it
+            Work::Visit(node @ AST::Catenation(_, _)) => {
+                let terms = terms(node);
+                work.push(Work::Catenation(terms.len()));
+                work.extend(terms.into_iter().rev().map(Work::Visit));
+            }
+            Work::Visit(node) => {
+                work.push(Work::Combine(node));
+                work.extend(node.children().into_iter().rev().map(Work::Visit));
+            }
+            Work::Catenation(count) => {
+                let terms = done.split_off(done.len() - count);
+                done.push(catenation(terms));
+            }
+            Work::Combine(node) => {
+                let children = done.split_off(done.len() - node.children().len());
+                done.push(combine(node, children));
+            }
+        }
+    }
+
+    let Facts {
+        prefix,
+        suffix,
+        required,
+        ..
+    } = done.pop().unwrap();
+    let inner = required
+        .into_iter()
+        .filter(|literal| !prefix.contains(literal.as_str()) && !suffix.contains(literal.as_str()))
+        .collect();
+    Literals {
+        prefix,
+        suffix,
+        inner,
+    }
+}
+
+/**
+ * The terms of a run of right-leaning catenations, in order.
+ */
+fn terms(mut ast: &AST) -> Vec<&AST> {
+    let mut terms = vec![];
+    while let AST::Catenation(lhs, rhs) = ast {
+        terms.push(&**lhs);
+        ast = rhs;
+    }
+    terms.push(ast);
+    terms
+}
+
+/**
+ * What is known about a run of catenated terms. The text from the suffix
+ * of one term that is not exact, through any exact terms, to the prefix of
+ * the next is required, as is everything the terms themselves require.
+ */
+fn catenation(terms: Vec<Facts>) -> Facts {
+    let mut prefix = String::new();
+    let mut in_prefix = true;
+    let mut running = String::new();
+    let mut required = vec![];
+    for term in terms {
+        match term.exact {
+            Some(text) => {
+                if in_prefix {
+                    prefix.push_str(&text);
+                }
+                running.push_str(&text);
+            }
+            None => {
+                if in_prefix {
+                    prefix.push_str(&term.prefix);
+                    in_prefix = false;
+                }
+                running.push_str(&term.prefix);
+                required.push(std::mem::replace(&mut running, term.suffix));
+                required.extend(term.required);
+            }
+        }
+    }
+    required.push(running.clone());
+    Facts {
+        exact: if in_prefix { Some(running.clone()) } else { None },
+        prefix,
+        suffix: running,
+        required: longest(required),
+    }
+}
+
+/**
+ * What is known about any other node, from what is known about its children.
+ */
+fn combine(node: &AST, mut children: Vec<Facts>) -> Facts {
+    match node {
+        AST::Char(c) => Facts::exact(c.to_string()),
+        AST::AnyChar | AST::Class(_) | AST::Closure(_) | AST::Complement(_) => Facts::unknown(),
+        // one or more repetitions start and end the way a single one does
+        AST::OneOrMore(_) => Facts {
+            exact: None,
+            ..children.pop().unwrap()
+        },
+        AST::Catenation(_, _) => catenation(children),
+        AST::Alternation(_, _) => {
+            let rhs = children.pop().unwrap();
+            let lhs = children.pop().unwrap();
+            if lhs.exact.is_some() && lhs.exact == rhs.exact {
+                return lhs;
+            }
+            let prefix = common_prefix(&lhs.prefix, &rhs.prefix);
+            let suffix = common_suffix(&lhs.suffix, &rhs.suffix);
+            let mut required: Vec<String> = lhs
+                .required
+                .into_iter()
+                .filter(|literal| rhs.required.contains(literal))
+                .collect();
+            required.push(prefix.clone());
+            required.push(suffix.clone());
+            Facts {
+                exact: None,
+                prefix,
+                suffix,
+                required: longest(required),
+            }
+        }
+        // a match of both sides has everything either side requires
+        AST::Intersection(_, _) => {
+            let rhs = children.pop().unwrap();
+            let lhs = children.pop().unwrap();
+            let longer = |lhs: String, rhs: String| if rhs.len() > lhs.len() { rhs } else { lhs };
+            let mut required = lhs.required;
+            required.extend(rhs.required);
+            Facts {
+                exact: lhs.exact.or(rhs.exact),
+                prefix: longer(lhs.prefix, rhs.prefix),
+                suffix: longer(lhs.suffix, rhs.suffix),
+                required: longest(required),
+            }
+        }
+        AST::Difference(_, _) => {
+            children.pop();
+            children.pop().unwrap()
+        }
+    }
+}
+
+/**
+ * Drop empty strings and strings contained in another one, since finding
+ * the longer string finds them too.
+ */
+fn longest(mut literals: Vec<String>) -> Vec<String> {
+    literals.sort_by(|lhs, rhs| rhs.len().cmp(&lhs.len()).then_with(|| lhs.cmp(rhs)));
+    let mut kept: Vec<String> = vec![];
+    for literal in literals {
+        if !literal.is_empty() && !kept.iter().any(|longer| longer.contains(literal.as_str())) {
+            kept.push(literal);
+        }
+    }
+    kept
+}
+
+fn common_prefix(lhs: &str, rhs: &str) -> String {
+    lhs.chars()
+        .zip(rhs.chars())
+        .take_while(|(lhs, rhs)| lhs == rhs)
+        .map(|(c, _)| c)
+        .collect()
+}
+
+fn common_suffix(lhs: &str, rhs: &str) -> String {
+    let reversed: String = lhs
+        .chars()
+        .rev()
+        .zip(rhs.chars().rev())
+        .take_while(|(lhs, rhs)| lhs == rhs)
+        .map(|(c, _)| c)
+        .collect();
+    reversed.chars().rev().collect()
+}
+
+#[cfg(test)]
+mod literals_tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::tokenizer::Tokenizer;
+
+    fn literals(pattern: &str) -> (String, String, Vec<String>) {
+        let literals = required_literals(&Parser::parse(Tokenizer::new(pattern)).unwrap());
+        (literals.prefix, literals.suffix, literals.inner)
+    }
+
+    fn strings(literals: &[&str]) -> Vec<String> {
+        literals.iter().map(|literal| literal.to_string()).collect()
+    }
+
+    #[test]
+    fn prefixes_and_suffixes() {
+        assert_eq!(literals("ERROR.*timeout"), ("ERROR".into(), "timeout".into(), vec![]));
+        assert_eq!(literals("abc"), ("abc".into(), "abc".into(), vec![]));
+        assert_eq!(literals("a+b"), ("a".into(), "ab".into(), vec![]));
+        assert_eq!(literals(".*"), ("".into(), "".into(), vec![]));
+    }
+
+    #[test]
+    fn inner_literals() {
+        assert_eq!(literals(".*foo[0-9]bar.*"), ("".into(), "".into(), strings(&["bar", "foo"])));
+        assert_eq!(literals("x*(ab)+y*"), ("".into(), "".into(), strings(&["ab"])));
+    }
+
+    #[test]
+    fn alternations() {
+        assert_eq!(literals("foobar|fooqux"), ("foo".into(), "".into(), vec![]));
+        assert_eq!(literals("a|b"), ("".into(), "".into(), vec![]));
+        assert_eq!(literals("(x.*err|y.*err)!"), ("".into(), "err!".into(), vec![]));
+    }
+
+    #[test]
+    fn prefilter() {
+        let literals = required_literals(&Parser::parse(Tokenizer::new("ERROR.*timeout")).unwrap());
+        assert!(literals.could_match("12:00 ERROR: read timeout"));
+        assert!(!literals.could_match("12:00 ERROR: bad request"));
+        assert!(!literals.could_match("12:00 INFO: timeout"));
+    }
+
+    #[test]
+    fn long_literals() {
+        let literal = "ab".repeat(5_000);
+        assert_eq!(literals(&literal), (literal.clone(), literal.clone(), vec![]));
+        let pattern = format!("x.{}.y", literal);
+        assert_eq!(literals(&pattern), ("x".into(), "y".into(), vec![literal]));
+    }
+}
@@ -0,0 +1,271 @@
+use super::super::nfa::NFA;
+use super::simplify::simplify;
+use super::{
+    build_alternation, build_anychar, build_catenation, build_char, build_class, build_closure,
+    build_complement, build_difference, build_intersection, build_one_or_more, normalize_ranges, AST,
+};
+use std::fmt;
+use std::ops::Not;
+
+/*
+ * Building patterns in code rather than by pasting strings together. Text
+ * given to a Pattern is always taken literally, so data can go into a
+ * pattern without any of its chars being read as operators; printing the
+ * pattern escapes whatever needs it.
+ */
+
+/**
+ * A pattern under construction, for example
+ * Pattern::literal("v").then(Pattern::range('0', '9').plus()).or(Pattern::literal("dev")).
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pattern {
+    ast: AST,
+}
+
+impl Pattern {
+    /**
+     * Exactly the given text, however many of its chars are operators.
+     */
+    pub fn literal(text: &str) -> Pattern {
+        let mut chars = text.chars().rev();
+        match chars.next() {
+            Some(last) => Pattern {
+                ast: chars.fold(build_char(last), |rest, c| build_catenation(build_char(c), rest)),
+            },
+            None => Pattern::empty(),
+        }
+    }
+
+    /**
+     * A single char.
+     */
+    pub fn char(c: char) -> Pattern {
+        Pattern { ast: build_char(c) }
+    }
+
+    /**
+     * Any single char, like a pattern's `.`.
+     */
+    pub fn any() -> Pattern {
+        Pattern { ast: build_anychar() }
+    }
+
+    /**
+     * One char from start to end inclusive, in either order.
+     */
+    pub fn range(start: char, end: char) -> Pattern {
+        Pattern::class(&[(start.min(end), start.max(end))])
+    }
+
+    /**
+     * One char from any of the given inclusive ranges, like a [class].
+     */
+    pub fn class(ranges: &[(char, char)]) -> Pattern {
+        Pattern {
+            ast: build_class(normalize_ranges(ranges.to_vec())),
+        }
+    }
+
+    /**
+     * Only the empty string.
+     */
+    pub fn empty() -> Pattern {
+        Pattern {
+            ast: build_closure(build_class(vec![])),
+        }
+    }
+
+    /**
+     * This pattern followed by another.
+     */
+    pub fn then(self, next: Pattern) -> Pattern {
+        // nested to the right, the way the parser nests a run of terms
+        let mut terms = vec![];
+        let mut ast = self.ast;
+        while let AST::Catenation(lhs, rhs) = ast {
+            terms.push(*lhs);
+            ast = *rhs;
+        }
+        terms.push(ast);
+        Pattern {
+            ast: terms.into_iter().rev().fold(next.ast, |rest, term| build_catenation(term, rest)),
+        }
+    }
+
+    /**
+     * Either this pattern or another.
+     */
+    pub fn or(self, other: Pattern) -> Pattern {
+        let mut branches = vec![];
+        let mut ast = self.ast;
+        while let AST::Alternation(lhs, rhs) = ast {
+            branches.push(*lhs);
+            ast = *rhs;
+        }
+        branches.push(ast);
+        Pattern {
+            ast: branches.into_iter().rev().fold(other.ast, |rest, branch| build_alternation(branch, rest)),
+        }
+    }
+
+    /**
+     * Strings both this pattern and another match, like `&`.
+     */
+    pub fn and(self, other: Pattern) -> Pattern {
+        Pattern {
+            ast: build_intersection(self.ast, other.ast),
+        }
+    }
+
+    /**
+     * Strings this pattern matches but another does not, like `-` in the
+     * extended syntax.
+     */
+    pub fn but_not(self, other: Pattern) -> Pattern {
+        Pattern {
+            ast: build_difference(self.ast, other.ast),
+        }
+    }
+
+    pub fn star(self) -> Pattern {
+        Pattern {
+            ast: build_closure(self.ast),
+        }
+    }
+
+    pub fn plus(self) -> Pattern {
+        Pattern {
+            ast: build_one_or_more(self.ast),
+        }
+    }
+
+    /**
+     * This pattern or the empty string.
+     */
+    pub fn optional(self) -> Pattern {
+        self.or(Pattern::empty())
+    }
+
+    /**
+     * From min to max repetitions, or at least min if there is no max,
+     * spelled out since the syntax has no counted repetition. Panics if max
+     * is less than min.
+     */
+    pub fn repeat(self, min: usize, max: Option<usize>) -> Pattern {
+        if let Some(max) = max {
+            assert!(min <= max, "repeat({}, Some({})) has max below min", min, max);
+        }
+        let tail = match max {
+            None => Some(self.clone().star()),
+            // each optional copy sits inside the one before, so no count is matched two ways
+            Some(max) => (min..max).fold(None, |tail: Option<Pattern>, _| {
+                Some(match tail {
+                    Some(tail) => self.clone().then(tail).optional(),
+                    None => self.clone().optional(),
+                })
+            }),
+        };
+        let pieces = (0..min).map(|_| self.clone()).chain(tail);
+        pieces.rev().fold(None, |rest: Option<Pattern>, piece| {
+            Some(match rest {
+                Some(rest) => piece.then(rest),
+                None => piece,
+            })
+        })
+        .unwrap_or_else(Pattern::empty)
+    }
+
+    pub fn ast(&self) -> &AST {
+        &self.ast
+    }
+
+    pub fn into_ast(self) -> AST {
+        self.ast
+    }
+
+    /**
+     * Compile the pattern, simplified the same way NFA::from simplifies a
+     * parsed one.
+     */
+    pub fn compile(&self) -> NFA {
+        NFA::from_ast(&simplify(self.ast.clone()))
+    }
+}
+
+/**
+ * Every string the pattern does not match, like `~`.
+ */
+impl Not for Pattern {
+    type Output = Pattern;
+
+    fn not(self) -> Pattern {
+        Pattern {
+            ast: build_complement(self.ast),
+        }
+    }
+}
+
+/**
+ * The pattern in the syntax thegrep reads, with every char that would
+ * otherwise be an operator escaped. It reads back the same way with
+ * Tokenizer::extended.
+ */
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.ast)
+    }
+}
+
+#[cfg(test)]
+mod pattern_tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::tokenizer::Tokenizer;
+
+    // the printed pattern should parse back to exactly what was built
+    fn printed(pattern: &Pattern) -> String {
+        let text = pattern.to_string();
+        assert_eq!(&Parser::parse(Tokenizer::extended(&text)).unwrap(), pattern.ast(), "{}", text);
+        text
+    }
+
+    #[test]
+    fn escaping() {
+        assert_eq!(printed(&Pattern::literal("1+1=2")), "1\\+1=2");
+        assert_eq!(printed(&Pattern::literal("a.b*(c)|[d]")), "a\\.b\\*\\(c\\)\\|\\[d]");
+        assert_eq!(printed(&Pattern::literal("x & ~y - z")), "x\\ \\&\\ \\~y\\ \\-\\ z");
+        let nfa = Pattern::literal("f(x)").compile();
+        assert!(nfa.accepts("y = f(x) + 1"));
+        assert!(!nfa.accepts("y = fx"));
+    }
+
+    #[test]
+    fn combinators() {
+        let version = Pattern::literal("v")
+            .then(Pattern::range('0', '9').plus())
+            .or(Pattern::literal("dev"));
+        assert_eq!(printed(&version), "v[0-9]+|dev");
+        let dotted = Pattern::literal("a.").then(Pattern::any().star());
+        assert_eq!(printed(&dotted), "a\\..*");
+        let words = Pattern::range('a', 'z').plus().and(!Pattern::literal("if").or(Pattern::literal("else")));
+        assert_eq!(printed(&words), "[a-z]+&~(if|else)");
+        assert_eq!(printed(&Pattern::any().star().but_not(Pattern::char('-'))), ".*-\\-");
+        let chain = Pattern::char('a').or(Pattern::char('b')).or(Pattern::char('c'));
+        assert_eq!(printed(&chain.then(Pattern::char('d')).then(Pattern::char('e'))), "(a|b|c)de");
+    }
+
+    #[test]
+    fn repetitions() {
+        let nfa = Pattern::literal("ab").repeat(2, Some(3)).compile();
+        assert_eq!(nfa.distinguish(&NFA::from("abab|ababab").unwrap()), None);
+        let nfa = Pattern::char('a').repeat(1, None).compile();
+        assert_eq!(nfa.distinguish(&NFA::from("a+").unwrap()), None);
+        let nfa = Pattern::char('a').repeat(0, Some(0)).compile();
+        assert!(nfa.matches_empty());
+        assert_eq!(nfa.max_length(), Some(0));
+        assert_eq!(Pattern::literal(""), Pattern::empty());
+        let nfa = Pattern::char('x').optional().compile();
+        assert_eq!(nfa.distinguish(&NFA::from("x|[]*").unwrap()), None);
+    }
+}
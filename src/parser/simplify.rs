@@ -0,0 +1,308 @@
+use super::{
+    build_alternation, build_catenation, build_char, build_class, build_closure,
+    build_complement, build_one_or_more, Fold, Shell, AST,
+};
+
+/*
+ * A rewrite pass that shrinks an AST without changing the strings it
+ * matches. NFA::from runs it before generating fragments, since every node
+ * removed here is a state, and every nested closure removed is an epsilon
+ * cycle, that the NFA no longer has to carry.
+ */
+
+/**
+ * Simplify an AST bottom up:
+ * - nested closures collapse, so (a*)*, (a+)*, (a*)+ and a** all become a*
+ * - double complements cancel, so ~~a becomes a
+ * - single chars and classes in an alternation merge into one class
+ * - alternatives sharing leading terms are factored, so abc|abd becomes ab[cd]
+ * - duplicate alternatives are dropped
+ * - groups that only restate precedence, like (ab)c, are flattened
+ *
+ * This folds the way AST::fold does, except that a whole run of
+ * alternations or catenations is taken apart and put back together at
+ * once, rather than a node at a time. Rebuilding the run at every node of
+ * it would make long literals and long lists of alternatives quadratic.
+ */
+pub fn simplify(ast: AST) -> AST {
+    // a node still to take apart, a run waiting on this many simplified
+    // operands, or a shell waiting on this many simplified children
+    enum Work {
+        Visit(AST),
+        Alternation(usize),
+        Catenation(usize),
+        Rebuild(Shell, usize),
+    }
+
+    let mut work = vec![Work::Visit(ast)];
+    let mut done: Vec<AST> = vec![];
+    while let Some(item) = work.pop() {
+        match item {
+            Work::Visit(node @ AST::Alternation(_, _)) => {
+                let branches = alternatives(node);
+                work.push(Work::Alternation(branches.len()));
+                work.extend(branches.into_iter().rev().map(Work::Visit));
+            }
+            Work::Visit(node @ AST::Catenation(_, _)) => {
+                let terms = terms(node);
+                work.push(Work::Catenation(terms.len()));
+                work.extend(terms.into_iter().rev().map(Work::Visit));
+            }
+            Work::Visit(node) => {
+                let (shell, children) = node.into_parts();
+                work.push(Work::Rebuild(shell, children.len()));
+                work.extend(children.into_iter().rev().map(Work::Visit));
+            }
+            // a simplified operand may itself be a run, from a group like (a|b)|c or (ab)c
+            Work::Alternation(count) => {
+                let branches = done.split_off(done.len() - count);
+                done.push(alternation(branches.into_iter().flat_map(alternatives).collect()));
+            }
+            Work::Catenation(count) => {
+                let all = done.split_off(done.len() - count);
+                done.push(catenation(all.into_iter().flat_map(terms).collect()));
+            }
+            Work::Rebuild(shell, count) => {
+                let children = done.split_off(done.len() - count);
+                done.push(Simplifier.fold(shell.rebuild(children)));
+            }
+        }
+    }
+    done.pop().unwrap()
+}
+
+/**
+ * The rules for every node other than a run of alternations or
+ * catenations. Children are already simplified by the time a node reaches
+ * `fold`, so each rule only has to look one level down.
+ */
+struct Simplifier;
+
+impl Fold for Simplifier {
+    fn fold(&mut self, ast: AST) -> AST {
+        match ast {
+            AST::Closure(ast) => match *ast {
+                AST::Closure(inner) | AST::OneOrMore(inner) => build_closure(*inner),
+                ast => build_closure(ast),
+            },
+            AST::OneOrMore(ast) => match *ast {
+                ast @ AST::Closure(_) | ast @ AST::OneOrMore(_) => ast,
+                ast => build_one_or_more(ast),
+            },
+            AST::Class(ranges) => class(ranges),
+            AST::Complement(ast) => match *ast {
+                AST::Complement(inner) => *inner,
+                ast => build_complement(ast),
+            },
+            leaf => leaf,
+        }
+    }
+}
+
+/**
+ * A class of a single char is just that char.
+ */
+fn class(ranges: Vec<(char, char)>) -> AST {
+    match ranges.as_slice() {
+        [(start, end)] if start == end => build_char(*start),
+        _ => build_class(ranges),
+    }
+}
+
+/**
+ * The branches of a run of right-leaning alternations, in order.
+ */
+fn alternatives(mut ast: AST) -> Vec<AST> {
+    let mut branches = vec![];
+    while let AST::Alternation(lhs, rhs) = ast {
+        branches.push(*lhs);
+        ast = *rhs;
+    }
+    branches.push(ast);
+    branches
+}
+
+/**
+ * The terms of a run of right-leaning catenations, in order.
+ */
+fn terms(mut ast: AST) -> Vec<AST> {
+    let mut terms = vec![];
+    while let AST::Catenation(lhs, rhs) = ast {
+        terms.push(*lhs);
+        ast = *rhs;
+    }
+    terms.push(ast);
+    terms
+}
+
+/**
+ * Rebuild a right-leaning catenation of already simplified terms.
+ */
+fn catenation(mut terms: Vec<AST>) -> AST {
+    let mut result = terms.pop().unwrap();
+    while let Some(term) = terms.pop() {
+        result = build_catenation(term, result);
+    }
+    result
+}
+
+/**
+ * Rebuild a right-leaning alternation of already simplified branches,
+ * deduplicating, merging chars into a class and factoring shared prefixes.
+ */
+fn alternation(branches: Vec<AST>) -> AST {
+    let mut unique: Vec<AST> = vec![];
+    for branch in branches {
+        if !unique.contains(&branch) {
+            unique.push(branch);
+        }
+    }
+
+    let mut branches = factor_prefixes(merge_chars(unique));
+    let mut result = branches.pop().unwrap();
+    while let Some(branch) = branches.pop() {
+        result = build_alternation(branch, result);
+    }
+    result
+}
+
+/**
+ * Merge every single char and class branch into one class, which takes the
+ * place of the first of them. They all disappear if AnyChar is a branch.
+ */
+fn merge_chars(branches: Vec<AST>) -> Vec<AST> {
+    let any = branches.contains(&AST::AnyChar);
+    let mut ranges = vec![];
+    let mut class_at = None;
+    let mut kept = vec![];
+
+    for branch in branches {
+        match branch {
+            AST::Char(c) => ranges.push((c, c)),
+            AST::Class(more) => ranges.extend(more),
+            branch => {
+                kept.push(branch);
+                continue;
+            }
+        }
+        if class_at.is_none() {
+            class_at = Some(kept.len());
+        }
+    }
+
+    if let Some(at) = class_at {
+        if !any {
+            kept.insert(at, class(ranges));
+        }
+    }
+    kept
+}
+
+/**
+ * Group branches that start with the same term and pull their longest
+ * common run of leading terms out in front of an alternation of what is
+ * left. Every branch must keep at least one term, since there is no way to
+ * write an empty alternative.
+ */
+fn factor_prefixes(branches: Vec<AST>) -> Vec<AST> {
+    let mut groups: Vec<Vec<Vec<AST>>> = vec![];
+    for branch in branches {
+        let branch = terms(branch);
+        match groups.iter_mut().find(|group| group[0][0] == branch[0]) {
+            Some(group) => group.push(branch),
+            None => groups.push(vec![branch]),
+        }
+    }
+
+    let mut factored = vec![];
+    for group in groups {
+        let shortest = group.iter().map(|branch| branch.len()).min().unwrap();
+        let shared = (0..shortest - 1)
+            .take_while(|&i| group.iter().all(|branch| branch[i] == group[0][i]))
+            .count();
+
+        if group.len() == 1 || shared == 0 {
+            factored.extend(group.into_iter().map(catenation));
+            continue;
+        }
+
+        let mut rests = vec![];
+        let mut prefix = vec![];
+        for mut branch in group {
+            rests.push(catenation(branch.split_off(shared)));
+            prefix = branch;
+        }
+        prefix.push(alternation(rests));
+        factored.push(catenation(prefix));
+    }
+    factored
+}
+
+#[cfg(test)]
+mod simplify_tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::tokenizer::Tokenizer;
+
+    fn simplified(pattern: &str) -> String {
+        simplify(Parser::parse(Tokenizer::new(pattern)).unwrap()).to_string()
+    }
+
+    #[test]
+    fn nested_closures() {
+        assert_eq!(simplified("(a*)*"), "a*");
+        assert_eq!(simplified("(a+)*"), "a*");
+        assert_eq!(simplified("(a*)+"), "a*");
+        assert_eq!(simplified("a**"), "a*");
+        assert_eq!(simplified("a++"), "a+");
+        assert_eq!(simplified("((ab)*)*c"), "(ab)*c");
+    }
+
+    #[test]
+    fn double_complements() {
+        assert_eq!(simplified("~~a"), "a");
+        assert_eq!(simplified("~~~(ab)"), "~(ab)");
+        assert_eq!(simplified("~(~a)*"), "~(~a)*");
+    }
+
+    #[test]
+    fn merges_chars_into_class() {
+        assert_eq!(simplified("a|b|c"), "[a-c]");
+        assert_eq!(simplified("a|[bc]|d"), "[a-d]");
+        assert_eq!(simplified("(ab|c)|d"), "ab|[cd]");
+        assert_eq!(simplified("x|.|y"), ".");
+        assert_eq!(simplified("[a]"), "a");
+    }
+
+    #[test]
+    fn factors_prefixes() {
+        assert_eq!(simplified("abc|abd"), "ab[cd]");
+        assert_eq!(simplified("foo|bar|fob"), "fo[bo]|bar");
+        assert_eq!(simplified("ab|a"), "ab|a");
+        assert_eq!(simplified("xab|xa"), "x(ab|a)");
+    }
+
+    #[test]
+    fn drops_duplicates() {
+        assert_eq!(simplified("foo|bar|foo"), "foo|bar");
+        assert_eq!(simplified("(a|a)*"), "a*");
+    }
+
+    #[test]
+    fn flattens_groups() {
+        assert_eq!(
+            simplify(Parser::parse(Tokenizer::new("(ab)(cd)")).unwrap()),
+            Parser::parse(Tokenizer::new("abcd")).unwrap()
+        );
+        assert_eq!(simplified("((a|b)c|d)|e"), "[ab]c|[de]");
+        assert_eq!(simplified("a(b(cd|ce))"), "abc[de]");
+    }
+
+    #[test]
+    fn long_runs() {
+        let literal = "ab".repeat(5_000);
+        assert_eq!(simplified(&literal), literal);
+        let branches: Vec<String> = (0..2_000).map(|i| format!("x{}", i)).collect();
+        assert!(simplified(&branches.join("|")).starts_with("x([0-9]|1([0-9]|0([0-9]|0[0-9]|1[0-9]|"));
+    }
+}
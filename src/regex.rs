@@ -0,0 +1,467 @@
+pub mod captures;
+pub mod template;
+
+use self::captures::{Captures, Program};
+use self::template::Template;
+use super::nfa::NFA;
+use super::parser::simplify::simplify;
+use super::parser::{Parser, DEFAULT_NEST_LIMIT};
+use super::tokenizer::Tokenizer;
+use std::fmt;
+
+/*
+ * thegrep as a library: a compiled pattern that can be searched for in
+ * text, for programs that would otherwise run thegrep and read its output.
+ * Matches are found the way egrep finds them, leftmost first and then
+ * longest, and their positions are byte offsets into the searched text.
+ */
+
+/**
+ * A compiled pattern. Compiling is the expensive part, so a Regex is meant
+ * to be built once and then used on any number of lines.
+ */
+#[derive(Debug)]
+pub struct Regex {
+    pattern: String,
+    nfa: NFA,
+    program: Program,
+}
+
+/**
+ * Options for compiling a Regex, for example
+ * RegexBuilder::new("[a-z]+ - (if|else)").extended(true).build().
+ */
+#[derive(Debug, Clone)]
+pub struct RegexBuilder {
+    pattern: String,
+    extended: bool,
+    nest_limit: usize,
+}
+
+/**
+ * Where a match, or a group within one, was found in the searched text.
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Match<'t> {
+    text: &'t str,
+    start: usize,
+    end: usize,
+}
+
+impl RegexBuilder {
+    pub fn new(pattern: &str) -> RegexBuilder {
+        RegexBuilder {
+            pattern: pattern.to_string(),
+            extended: false,
+            nest_limit: DEFAULT_NEST_LIMIT,
+        }
+    }
+
+    /**
+     * Read a dash outside of a [class] as set difference, like thegrep -x.
+     */
+    pub fn extended(&mut self, extended: bool) -> &mut RegexBuilder {
+        self.extended = extended;
+        self
+    }
+
+    /**
+     * How many groups may be open at once before compiling gives up.
+     */
+    pub fn nest_limit(&mut self, nest_limit: usize) -> &mut RegexBuilder {
+        self.nest_limit = nest_limit;
+        self
+    }
+
+    /**
+     * Compile the pattern, or say what is wrong with it.
+     */
+    pub fn build(&self) -> Result<Regex, String> {
+        let (ast, spans) = Parser::parse_spanned_with_limit(self.tokenizer(), self.nest_limit)?;
+        let program = Program::new(self.tokenizer(), &ast, &spans);
+        Ok(Regex {
+            pattern: self.pattern.clone(),
+            nfa: NFA::from_ast(&simplify(ast)),
+            program,
+        })
+    }
+
+    fn tokenizer(&self) -> Tokenizer<'_> {
+        if self.extended {
+            Tokenizer::extended(&self.pattern)
+        } else {
+            Tokenizer::new(&self.pattern)
+        }
+    }
+}
+
+impl Regex {
+    /**
+     * Compile a pattern with the default options.
+     */
+    pub fn new(pattern: &str) -> Result<Regex, String> {
+        RegexBuilder::new(pattern).build()
+    }
+
+    /**
+     * The pattern as it was given.
+     */
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    /**
+     * How many groups the pattern has, counting the whole match as group 0.
+     */
+    pub fn captures_len(&self) -> usize {
+        self.program.group_count() + 1
+    }
+
+    /**
+     * Whether the pattern matches somewhere in the text.
+     */
+    pub fn is_match(&self, text: &str) -> bool {
+        self.find(text).is_some()
+    }
+
+    /**
+     * The first match in the text.
+     */
+    pub fn find<'t>(&self, text: &'t str) -> Option<Match<'t>> {
+        self.find_iter(text).next()
+    }
+
+    /**
+     * Every match in the text, from left to right, none overlapping.
+     */
+    pub fn find_iter<'r, 't>(&'r self, text: &'t str) -> Matches<'r, 't> {
+        let chars: Vec<char> = text.chars().collect();
+        let offsets = text.char_indices().map(|(offset, _)| offset).chain(Some(text.len())).collect();
+        Matches {
+            regex: self,
+            text,
+            chars,
+            offsets,
+            // no text without what every match needs can hold one, so that is not searched at all
+            at: if self.nfa.could_match(text) { 0 } else { usize::MAX },
+            last_end: None,
+        }
+    }
+
+    /**
+     * The first match in the text along with what each group matched.
+     */
+    pub fn captures<'t>(&self, text: &'t str) -> Option<Captures<'t>> {
+        self.find(text).map(|found| self.captures_of(found))
+    }
+
+    /**
+     * What each group matched within a match.
+     */
+    fn captures_of<'t>(&self, found: Match<'t>) -> Captures<'t> {
+        let chars: Vec<char> = found.as_str().chars().collect();
+        let offsets: Vec<usize> = found
+            .as_str()
+            .char_indices()
+            .map(|(offset, _)| found.start + offset)
+            .chain(Some(found.end))
+            .collect();
+        let slots = self
+            .program
+            .captures(&chars)
+            .into_iter()
+            .map(|slot| slot.map(|(start, end)| (offsets[start], offsets[end])))
+            .collect();
+        Captures {
+            text: found.text,
+            slots,
+            names: self.program.names().to_vec(),
+        }
+    }
+
+    /**
+     * The text with its first match replaced by a template; see Template
+     * for how one is written.
+     */
+    pub fn replace(&self, text: &str, template: &str) -> String {
+        self.replacen(text, 1, &Template::new(template))
+    }
+
+    /**
+     * The text with every match replaced by a template.
+     */
+    pub fn replace_all(&self, text: &str, template: &str) -> String {
+        self.replacen(text, 0, &Template::new(template))
+    }
+
+    /**
+     * The text with its first limit matches replaced by a template, or
+     * every match if the limit is 0.
+     */
+    pub fn replacen(&self, text: &str, limit: usize, template: &Template) -> String {
+        let limit = if limit == 0 { usize::MAX } else { limit };
+        let mut out = String::with_capacity(text.len());
+        let mut last = 0;
+        for found in self.find_iter(text).take(limit) {
+            out.push_str(&text[last..found.start]);
+            // the groups are only worked out when the template has a use for them
+            let captures = if template.uses_groups() {
+                self.captures_of(found)
+            } else {
+                Captures {
+                    text,
+                    slots: vec![Some((found.start, found.end))],
+                    names: vec![],
+                }
+            };
+            template.expand(&captures, &mut out);
+            last = found.end;
+        }
+        out.push_str(&text[last..]);
+        out
+    }
+
+    /**
+     * Check that every group a template refers to is in the pattern. A
+     * missing group just adds nothing, so this is how a typo in a group
+     * name gets caught.
+     */
+    pub fn check_template(&self, template: &Template) -> Result<(), String> {
+        match template.missing(self.program.group_count(), self.program.names()).first() {
+            Some(group) => Err(format!("the pattern has no group {}", group)),
+            None => Ok(()),
+        }
+    }
+
+    /**
+     * The pieces of the text between matches. Text with n matches always
+     * has n + 1 pieces, some of which may be empty.
+     */
+    pub fn split<'r, 't>(&'r self, text: &'t str) -> Split<'r, 't> {
+        Split {
+            matches: self.find_iter(text),
+            last: 0,
+            done: false,
+        }
+    }
+
+    /**
+     * At most limit pieces of the text between matches, the last of them
+     * being the rest of the text after the matches before it.
+     */
+    pub fn splitn<'r, 't>(&'r self, text: &'t str, limit: usize) -> SplitN<'r, 't> {
+        SplitN {
+            split: self.split(text),
+            limit,
+        }
+    }
+}
+
+/**
+ * A Regex prints as the pattern it was compiled from.
+ */
+impl fmt::Display for Regex {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.pattern)
+    }
+}
+
+impl<'t> Match<'t> {
+    /**
+     * The byte offset the match starts at.
+     */
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /**
+     * The byte offset just after the match.
+     */
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    pub fn as_str(&self) -> &'t str {
+        &self.text[self.start..self.end]
+    }
+}
+
+/**
+ * The matches of a Regex in some text, from Regex::find_iter.
+ */
+pub struct Matches<'r, 't> {
+    regex: &'r Regex,
+    text: &'t str,
+    chars: Vec<char>,
+    // byte offset of each char, and then of the end of the text
+    offsets: Vec<usize>,
+    // the char to search on from
+    at: usize,
+    last_end: Option<usize>,
+}
+
+impl<'r, 't> Iterator for Matches<'r, 't> {
+    type Item = Match<'t>;
+
+    fn next(&mut self) -> Option<Match<'t>> {
+        loop {
+            let (start, end) = self.regex.nfa.find_at(&self.chars, self.at)?;
+            // an empty match goes no further, so the search after it starts a char later
+            self.at = if end > start { end } else { end + 1 };
+            // and there is none right where the last match ended, inside it as far as anyone can tell
+            if start == end && self.last_end == Some(end) {
+                continue;
+            }
+            self.last_end = Some(end);
+            return Some(Match {
+                text: self.text,
+                start: self.offsets[start],
+                end: self.offsets[end],
+            });
+        }
+    }
+}
+
+/**
+ * The pieces of some text between matches of a Regex, from Regex::split.
+ */
+pub struct Split<'r, 't> {
+    matches: Matches<'r, 't>,
+    // byte offset of the start of the next piece
+    last: usize,
+    done: bool,
+}
+
+impl<'r, 't> Iterator for Split<'r, 't> {
+    type Item = &'t str;
+
+    fn next(&mut self) -> Option<&'t str> {
+        if self.done {
+            return None;
+        }
+        let text = self.matches.text;
+        match self.matches.next() {
+            Some(found) => {
+                let piece = &text[self.last..found.start];
+                self.last = found.end;
+                Some(piece)
+            }
+            None => {
+                self.done = true;
+                Some(&text[self.last..])
+            }
+        }
+    }
+}
+
+/**
+ * The pieces of some text between its first few matches of a Regex, from
+ * Regex::splitn.
+ */
+pub struct SplitN<'r, 't> {
+    split: Split<'r, 't>,
+    // how many more pieces there may be
+    limit: usize,
+}
+
+impl<'r, 't> Iterator for SplitN<'r, 't> {
+    type Item = &'t str;
+
+    fn next(&mut self) -> Option<&'t str> {
+        match self.limit {
+            0 => None,
+            1 => {
+                self.limit = 0;
+                if self.split.done {
+                    return None;
+                }
+                self.split.done = true;
+                Some(&self.split.matches.text[self.split.last..])
+            }
+            _ => {
+                self.limit -= 1;
+                self.split.next()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod regex_tests {
+    use super::*;
+
+    fn found(pattern: &str, text: &str) -> Vec<(usize, usize)> {
+        Regex::new(pattern)
+            .unwrap()
+            .find_iter(text)
+            .map(|found| (found.start(), found.end()))
+            .collect()
+    }
+
+    #[test]
+    fn matching() {
+        let regex = Regex::new("[0-9]+").unwrap();
+        assert!(regex.is_match("abc 123"));
+        assert!(!regex.is_match("abc"));
+        assert_eq!(regex.find("abc 123 45").map(|found| found.as_str()), Some("123"));
+        assert_eq!(regex.as_str(), "[0-9]+");
+        assert!(Regex::new("a(b").is_err());
+    }
+
+    #[test]
+    fn every_match() {
+        assert_eq!(found("ab", "ab ab xab"), vec![(0, 2), (3, 5), (7, 9)]);
+        assert_eq!(found("a*", "baab"), vec![(0, 0), (1, 3), (4, 4)]);
+        assert_eq!(found("x*", ""), vec![(0, 0)]);
+        assert_eq!(found("q", "abc"), vec![]);
+    }
+
+    #[test]
+    fn byte_offsets() {
+        let regex = Regex::new("é+").unwrap();
+        let found = regex.find("caféé!").unwrap();
+        assert_eq!((found.start(), found.end()), (3, 7));
+        assert_eq!(found.as_str(), "éé");
+        assert_eq!(&regex.captures("xé").unwrap()[0], "é");
+    }
+
+    #[test]
+    fn replacing_and_splitting() {
+        let regex = Regex::new(",\\ *").unwrap();
+        assert_eq!(regex.replace("a,  b, c", "; "), "a; b, c");
+        assert_eq!(regex.replace_all("a,  b, c", "; "), "a; b; c");
+        assert_eq!(regex.replace("abc", "; "), "abc");
+        assert_eq!(regex.split("a,  b,c").collect::<Vec<_>>(), vec!["a", "b", "c"]);
+        assert_eq!(regex.split(",a,").collect::<Vec<_>>(), vec!["", "a", ""]);
+        assert_eq!(regex.split("").collect::<Vec<_>>(), vec![""]);
+        assert_eq!(regex.splitn("a, b, c", 2).collect::<Vec<_>>(), vec!["a", "b, c"]);
+        assert_eq!(regex.splitn("a, b", 5).collect::<Vec<_>>(), vec!["a", "b"]);
+        assert_eq!(regex.splitn("a, b", 1).collect::<Vec<_>>(), vec!["a, b"]);
+        assert_eq!(regex.splitn("a, b", 0).count(), 0);
+        let fields = Regex::new("\\ *[|;]\\ *").unwrap();
+        assert_eq!(fields.split("id | name;age").collect::<Vec<_>>(), vec!["id", "name", "age"]);
+    }
+
+    #[test]
+    fn options() {
+        let regex = RegexBuilder::new("[a-z]+ - (if|else)").extended(true).build().unwrap();
+        assert_eq!(regex.find("if x").map(|found| found.as_str()), Some("i"));
+        assert_eq!(regex.captures_len(), 2);
+        assert!(RegexBuilder::new("((a))").nest_limit(1).build().is_err());
+        assert!(RegexBuilder::new("(a)").nest_limit(1).build().is_ok());
+    }
+
+    #[test]
+    fn checking_templates() {
+        let regex = Regex::new("(?<key>[a-z]+)=([0-9]+)").unwrap();
+        assert_eq!(regex.check_template(&Template::new("$key $2 $0")), Ok(()));
+        assert_eq!(
+            regex.check_template(&Template::new("$3")),
+            Err(String::from("the pattern has no group 3"))
+        );
+        assert_eq!(
+            regex.check_template(&Template::new("${value}")),
+            Err(String::from("the pattern has no group value"))
+        );
+    }
+}
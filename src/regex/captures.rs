@@ -0,0 +1,549 @@
+use super::super::nfa::dfa::DFA;
+use super::super::nfa::NFA;
+use super::super::parser::{SpanTree, AST};
+use super::super::tokenizer::{Span, Token, Tokenizer};
+use super::Match;
+use std::collections::HashSet;
+use std::ops::Index;
+
+/*
+ * Working out what each parenthesized group of a pattern matched. The NFA
+ * only knows where a whole match starts and ends, since simplification
+ * throws the groups away, so the pattern as written is compiled a second
+ * time, into a program that records where each group starts and ends as
+ * it goes. Running it over just the matched text, all of its threads at
+ * once and in order of preference, takes time linear in the length of the
+ * match. An intersection, difference or complement has no such program, so
+ * it runs as a DFA of its own, and the groups on the left of an
+ * intersection or difference are found afterwards by running the program
+ * for that side over the text the DFA matched.
+ */
+
+/**
+ * The text a match and each group of the pattern matched, numbered by
+ * where their left parens are with 0 for the whole match. A group left
+ * out of the match, like the second in `(a)|(b)` matching "a", has none.
+ */
+#[derive(Debug)]
+pub struct Captures<'t> {
+    pub(super) text: &'t str,
+    pub(super) slots: Vec<Option<(usize, usize)>>,
+    // the number of each group written (?<name>
+    pub(super) names: Vec<(String, usize)>,
+}
+
+impl<'t> Captures<'t> {
+    /**
+     * What group i matched, if it took part in the match.
+     */
+    pub fn get(&self, i: usize) -> Option<Match<'t>> {
+        self.slots.get(i).cloned().flatten().map(|(start, end)| Match {
+            text: self.text,
+            start,
+            end,
+        })
+    }
+
+    /**
+     * What the group written (?<name> matched, if it took part in the
+     * match. When several groups share a name, the first of them to take
+     * part counts.
+     */
+    pub fn name(&self, name: &str) -> Option<Match<'t>> {
+        self.names
+            .iter()
+            .filter(|(group, _)| group == name)
+            .find_map(|(_, i)| self.get(*i))
+    }
+
+    /**
+     * How many groups there are, the whole match included.
+     */
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+}
+
+/**
+ * The text group i matched, which panics if there is no group i and is
+ * empty if it took no part in the match.
+ */
+impl<'t> Index<usize> for Captures<'t> {
+    type Output = str;
+
+    fn index(&self, i: usize) -> &str {
+        assert!(i < self.len(), "no group {}", i);
+        self.get(i).map_or("", |found| found.as_str())
+    }
+}
+
+/**
+ * A pattern's AST as a list of nodes, children before their parents, with
+ * the groups written around each node, compiled into the programs that
+ * find what the groups matched.
+ */
+#[derive(Debug)]
+pub(super) struct Program {
+    nodes: Vec<Node>,
+    groups: Vec<Vec<usize>>,
+    count: usize,
+    names: Vec<(String, usize)>,
+    // the DFA for each intersection, difference or complement
+    dfas: Vec<DFA>,
+    // the program for the whole pattern, followed by one for the lhs of each set operator in it
+    codes: Vec<Code>,
+}
+
+#[derive(Debug)]
+enum Node {
+    Any,
+    Char(char),
+    Class(Vec<(char, char)>),
+    Catenation(usize, usize),
+    Alternation(usize, usize),
+    Closure(usize),
+    OneOrMore(usize),
+    // a set operator, as the index of its DFA and, unless it is a complement, its lhs
+    Set(usize, Option<usize>),
+}
+
+/**
+ * The instructions of one program. Each one's last field is the
+ * instruction it goes on to; a split goes on to both of its, preferring the
+ * first, and a Set runs the DFA it names for as long as that can match.
+ */
+#[derive(Debug)]
+enum Inst {
+    Any(usize),
+    Char(char, usize),
+    Class(Vec<(char, char)>, usize),
+    Split(usize, usize),
+    Save(usize, usize),
+    Set(usize, usize),
+    Match,
+}
+
+// where an instruction goes on to before it is known
+const HOLE: usize = usize::MAX;
+
+/**
+ * A compiled program, with the slot for each group's start and end saved
+ * at 2i and 2i + 1, and those of each of its Set instructions after them.
+ */
+#[derive(Debug)]
+struct Code {
+    insts: Vec<Inst>,
+    start: usize,
+    // for each Set instruction, its DFA and the program for its lhs, if there is one
+    sets: Vec<(usize, Option<usize>)>,
+}
+
+// the first instruction of a node's code and the instructions that go on from it
+struct Fragment {
+    start: usize,
+    outs: Vec<usize>,
+}
+
+impl Code {
+    fn push(&mut self, inst: Inst) -> usize {
+        self.insts.push(inst);
+        self.insts.len() - 1
+    }
+
+    // fill in where each of the instructions goes on to
+    fn patch(&mut self, outs: &[usize], to: usize) {
+        for out in outs {
+            match &mut self.insts[*out] {
+                Inst::Any(next)
+                | Inst::Char(_, next)
+                | Inst::Class(_, next)
+                | Inst::Split(_, next)
+                | Inst::Save(_, next)
+                | Inst::Set(_, next) => *next = to,
+                Inst::Match => {}
+            }
+        }
+    }
+}
+
+// a thread of a running program: the instruction it is on, the state of the DFA it is
+// running if that is a Set instruction, and the slots it has saved
+type Thread = (usize, usize, Vec<Option<usize>>);
+
+impl Program {
+    /**
+     * Lay out the AST of a pattern, which must be the one parsed from it
+     * with spans, before any simplification.
+     */
+    pub(super) fn new(pattern: Tokenizer, ast: &AST, spans: &SpanTree) -> Program {
+        let mut nodes = vec![];
+        let mut node_spans = vec![];
+        let mut dfas = vec![];
+        let mut done: Vec<usize> = vec![];
+        let mut stack = vec![(ast, spans, false)];
+        while let Some((ast, tree, children_done)) = stack.pop() {
+            if !children_done {
+                stack.push((ast, tree, true));
+                for (child, child_tree) in ast.children().into_iter().zip(&tree.children).rev() {
+                    stack.push((child, child_tree, false));
+                }
+                continue;
+            }
+            let children = done.split_off(done.len() - ast.children().len());
+            let mut set = |lhs: Option<usize>| {
+                dfas.push(NFA::from_ast(ast).to_dfa());
+                Node::Set(dfas.len() - 1, lhs)
+            };
+            nodes.push(match ast {
+                AST::AnyChar => Node::Any,
+                AST::Char(c) => Node::Char(*c),
+                AST::Class(ranges) => Node::Class(ranges.clone()),
+                AST::Catenation(_, _) => Node::Catenation(children[0], children[1]),
+                AST::Alternation(_, _) => Node::Alternation(children[0], children[1]),
+                AST::Closure(_) => Node::Closure(children[0]),
+                AST::OneOrMore(_) => Node::OneOrMore(children[0]),
+                AST::Intersection(_, _) | AST::Difference(_, _) => set(Some(children[0])),
+                AST::Complement(_) => set(None),
+            });
+            node_spans.push(tree.span);
+            done.push(nodes.len() - 1);
+        }
+
+        // a group belongs to the innermost node around it; spans of nodes nest like the
+        // nodes do, and children come first, so that is the first one found
+        let mut groups = vec![vec![]; nodes.len()];
+        let mut names = vec![];
+        let group_spans = group_spans(pattern);
+        for (at, (group, name)) in group_spans.iter().enumerate() {
+            let within = |span: &Span| span.start <= group.start && group.end <= span.end;
+            if let Some(node) = node_spans.iter().position(within) {
+                groups[node].push(at + 1);
+            }
+            if let Some(name) = name {
+                names.push((name.clone(), at + 1));
+            }
+        }
+        let mut program = Program {
+            nodes,
+            groups,
+            count: group_spans.len(),
+            names,
+            dfas,
+            codes: vec![],
+        };
+
+        // compiling a set operator asks for a program for its lhs, which comes later
+        let mut roots = vec![program.nodes.len() - 1];
+        while program.codes.len() < roots.len() {
+            let code = program.compile(roots[program.codes.len()], &mut roots);
+            program.codes.push(code);
+        }
+        program
+    }
+
+    /**
+     * The number of each named group.
+     */
+    pub(super) fn names(&self) -> &[(String, usize)] {
+        &self.names
+    }
+
+    /**
+     * How many groups the pattern has, not counting the whole match.
+     */
+    pub(super) fn group_count(&self) -> usize {
+        self.count
+    }
+
+    /**
+     * Compile the nodes under a root, adding the root of the lhs of each
+     * set operator among them to roots so that it gets a program too.
+     */
+    fn compile(&self, root: usize, roots: &mut Vec<usize>) -> Code {
+        // the nodes to compile, parents before children, leaving out what is under a set operator
+        let mut order = vec![];
+        let mut stack = vec![root];
+        while let Some(node) = stack.pop() {
+            order.push(node);
+            match self.nodes[node] {
+                Node::Catenation(lhs, rhs) | Node::Alternation(lhs, rhs) => stack.extend(&[lhs, rhs]),
+                Node::Closure(body) | Node::OneOrMore(body) => stack.push(body),
+                _ => {}
+            }
+        }
+
+        let mut code = Code {
+            insts: vec![],
+            start: 0,
+            sets: vec![],
+        };
+        let slots = 2 * (self.count + 1);
+        let mut fragments: Vec<Option<Fragment>> = (0..self.nodes.len()).map(|_| None).collect();
+        for node in order.into_iter().rev() {
+            let mut take = |child: usize| fragments[child].take().unwrap();
+            let mut fragment = match &self.nodes[node] {
+                Node::Any => {
+                    let at = code.push(Inst::Any(HOLE));
+                    Fragment { start: at, outs: vec![at] }
+                }
+                Node::Char(c) => {
+                    let at = code.push(Inst::Char(*c, HOLE));
+                    Fragment { start: at, outs: vec![at] }
+                }
+                Node::Class(ranges) => {
+                    let at = code.push(Inst::Class(ranges.clone(), HOLE));
+                    Fragment { start: at, outs: vec![at] }
+                }
+                Node::Catenation(lhs, rhs) => {
+                    let (lhs, rhs) = (take(*lhs), take(*rhs));
+                    code.patch(&lhs.outs, rhs.start);
+                    Fragment {
+                        start: lhs.start,
+                        outs: rhs.outs,
+                    }
+                }
+                Node::Alternation(lhs, rhs) => {
+                    let (lhs, rhs) = (take(*lhs), take(*rhs));
+                    let at = code.push(Inst::Split(lhs.start, rhs.start));
+                    Fragment {
+                        start: at,
+                        outs: [lhs.outs, rhs.outs].concat(),
+                    }
+                }
+                Node::Closure(body) | Node::OneOrMore(body) => {
+                    let body = take(*body);
+                    let at = code.push(Inst::Split(body.start, HOLE));
+                    code.patch(&body.outs, at);
+                    let start = if let Node::Closure(_) = self.nodes[node] { at } else { body.start };
+                    Fragment { start, outs: vec![at] }
+                }
+                // the slots after the groups' hold where each set operator started and ended
+                Node::Set(dfa, lhs) => {
+                    let lhs = lhs.map(|lhs| {
+                        roots.push(lhs);
+                        roots.len() - 1
+                    });
+                    let slot = slots + 2 * code.sets.len();
+                    code.sets.push((*dfa, lhs));
+                    let end = code.push(Inst::Save(slot + 1, HOLE));
+                    let set = code.push(Inst::Set(code.sets.len() - 1, end));
+                    let start = code.push(Inst::Save(slot, set));
+                    Fragment { start, outs: vec![end] }
+                }
+            };
+            for group in &self.groups[node] {
+                let end = code.push(Inst::Save(2 * group + 1, HOLE));
+                code.patch(&fragment.outs, end);
+                let start = code.push(Inst::Save(2 * group, fragment.start));
+                fragment = Fragment { start, outs: vec![end] };
+            }
+            fragments[node] = Some(fragment);
+        }
+
+        let fragment = fragments[root].take().unwrap();
+        let done = code.push(Inst::Match);
+        code.patch(&fragment.outs, done);
+        code.start = fragment.start;
+        code
+    }
+
+    /**
+     * The char ranges each group matched within a match covering the
+     * whole of chars, starting at offset 0 for the whole match.
+     */
+    pub(super) fn captures(&self, chars: &[char]) -> Vec<Option<(usize, usize)>> {
+        let mut slots = vec![None; self.count + 1];
+        slots[0] = Some((0, chars.len()));
+
+        // each program to run, over the chars from an offset up to another
+        let mut runs = vec![(0, 0, chars.len())];
+        while let Some((code, from, to)) = runs.pop() {
+            let saved = match self.run(&self.codes[code], &chars[from..to]) {
+                Some(saved) => saved,
+                None => continue,
+            };
+            for (group, slot) in slots.iter_mut().enumerate().skip(1) {
+                if let (Some(start), Some(end)) = (saved[2 * group], saved[2 * group + 1]) {
+                    *slot = Some((from + start, from + end));
+                }
+            }
+            let sets = saved[2 * (self.count + 1)..].chunks(2);
+            for ((_, lhs), span) in self.codes[code].sets.iter().zip(sets) {
+                if let (Some(lhs), Some(start), Some(end)) = (lhs, span[0], span[1]) {
+                    runs.push((*lhs, from + start, from + end));
+                }
+            }
+        }
+        slots
+    }
+
+    /**
+     * Run a program over the whole of chars, returning the slots saved by
+     * the thread it likes best of those that match all of it.
+     */
+    fn run(&self, code: &Code, chars: &[char]) -> Option<Vec<Option<usize>>> {
+        let slots = 2 * (self.count + 1 + code.sets.len());
+        let mut threads = vec![];
+        let mut seen = HashSet::new();
+        self.add_thread(code, &mut threads, &mut seen, 0, (code.start, 0, vec![None; slots]));
+        for (at, c) in chars.iter().enumerate() {
+            let mut next = vec![];
+            seen.clear();
+            for (inst, state, saved) in threads {
+                let to = match &code.insts[inst] {
+                    Inst::Any(to) => (*to, 0),
+                    Inst::Char(expected, to) if expected == c => (*to, 0),
+                    Inst::Class(ranges, to) if ranges.iter().any(|(start, end)| start <= c && c <= end) => (*to, 0),
+                    Inst::Set(set, _) => (inst, self.dfas[code.sets[*set].0].step(state, *c)),
+                    _ => continue,
+                };
+                self.add_thread(code, &mut next, &mut seen, at + 1, (to.0, to.1, saved));
+            }
+            threads = next;
+            if threads.is_empty() {
+                return None;
+            }
+        }
+        threads
+            .into_iter()
+            .find(|(inst, _, _)| matches!(code.insts[*inst], Inst::Match))
+            .map(|(_, _, saved)| saved)
+    }
+
+    /**
+     * Add a thread at a position, and every thread it leads to without
+     * reading a char, in order of preference, unless a thread liked better
+     * got to the same instruction there first.
+     */
+    fn add_thread(
+        &self,
+        code: &Code,
+        threads: &mut Vec<Thread>,
+        seen: &mut HashSet<(usize, usize)>,
+        at: usize,
+        thread: Thread,
+    ) {
+        let mut stack = vec![thread];
+        while let Some((inst, state, mut saved)) = stack.pop() {
+            if !seen.insert((inst, state)) {
+                continue;
+            }
+            match &code.insts[inst] {
+                Inst::Split(first, second) => {
+                    stack.push((*second, 0, saved.clone()));
+                    stack.push((*first, 0, saved));
+                }
+                Inst::Save(slot, to) => {
+                    saved[*slot] = Some(at);
+                    stack.push((*to, 0, saved));
+                }
+                // a set operator that could stop here can also go on, though staying is liked better
+                Inst::Set(set, to) => {
+                    if self.dfas[code.sets[*set].0].is_accepting(state) {
+                        stack.push((*to, 0, saved.clone()));
+                    }
+                    threads.push((inst, state, saved));
+                }
+                _ => threads.push((inst, state, saved)),
+            }
+        }
+    }
+}
+
+/**
+ * The spans of the groups of a pattern, from each left paren to the right
+ * paren that closes it, in the order of the left parens, along with any
+ * names they were given.
+ */
+fn group_spans(mut pattern: Tokenizer) -> Vec<(Span, Option<String>)> {
+    let mut spans: Vec<(Span, Option<String>)> = vec![];
+    let mut open = vec![];
+    while let Some((token, span)) = pattern.next_spanned() {
+        match token {
+            Token::LParen => {
+                open.push(spans.len());
+                spans.push((span, None));
+            }
+            Token::NamedLParen(name) => {
+                open.push(spans.len());
+                spans.push((span, Some(name)));
+            }
+            Token::RParen => {
+                if let Some(at) = open.pop() {
+                    spans[at].0.end = span.end;
+                }
+            }
+            _ => {}
+        }
+    }
+    spans
+}
+
+#[cfg(test)]
+mod captures_tests {
+    use crate::regex::Regex;
+
+    fn groups(pattern: &str, text: &str) -> Vec<Option<String>> {
+        let captures = Regex::new(pattern).unwrap().captures(text).unwrap();
+        (0..captures.len()).map(|i| captures.get(i).map(|found| found.as_str().to_string())).collect()
+    }
+
+    fn some(texts: &[&str]) -> Vec<Option<String>> {
+        texts.iter().map(|text| Some(text.to_string())).collect()
+    }
+
+    #[test]
+    fn numbered_groups() {
+        assert_eq!(groups("(a+)(b+)", "xaabbby"), some(&["aabbb", "aa", "bbb"]));
+        assert_eq!(groups("([0-9]+)-([0-9]+)", "call 555-1234"), some(&["555-1234", "555", "1234"]));
+        assert_eq!(groups("((a)(b))c", "abc"), some(&["abc", "ab", "a", "b"]));
+    }
+
+    #[test]
+    fn groups_left_out() {
+        assert_eq!(groups("(a)|(b)", "b"), vec![Some("b".into()), None, Some("b".into())]);
+        assert_eq!(groups("x(a)*", "x"), vec![Some("x".into()), None]);
+    }
+
+    #[test]
+    fn repeated_groups() {
+        assert_eq!(groups("(ab|c)+", "abcab"), some(&["abcab", "ab"]));
+        assert_eq!(groups("(a*)(a*)", "aaa"), some(&["aaa", "aaa", ""]));
+        assert_eq!(groups("((a))", "a"), some(&["a", "a", "a"]));
+    }
+
+    #[test]
+    fn named_groups() {
+        let regex = Regex::new("(?<year>[0-9]+)-(?<month>[0-9]+)").unwrap();
+        let captures = regex.captures("on 2019-04").unwrap();
+        assert_eq!(captures.name("year").map(|found| found.as_str()), Some("2019"));
+        assert_eq!(captures.name("month").map(|found| found.as_str()), Some("04"));
+        assert_eq!(&captures[2], "04");
+        assert_eq!(captures.name("day"), None);
+        let either = Regex::new("(?<n>a)|(?<n>b)").unwrap().captures("b").unwrap();
+        assert_eq!(either.name("n").map(|found| found.as_str()), Some("b"));
+    }
+
+    #[test]
+    fn set_operators() {
+        let regex = crate::regex::RegexBuilder::new("([a-z]+)-(if)").extended(true).build().unwrap();
+        let captures = regex.captures("if else").unwrap();
+        assert_eq!(&captures[0], "i");
+        assert_eq!(&captures[1], "i");
+        assert_eq!(captures.get(2), None);
+        assert_eq!(groups("((a+)b&.*b)c", "xaabc"), some(&["aabc", "aab", "aa"]));
+        assert_eq!(groups("x(~(y))", "xz"), vec![Some("xz".into()), Some("z".into()), None]);
+    }
+
+    #[test]
+    fn long_matches() {
+        let text = format!("{}foo", "x".repeat(20_000));
+        let captures = Regex::new("(.*)foo").unwrap().captures(&text).unwrap();
+        assert_eq!(captures[1].len(), 20_000);
+        assert_eq!(Regex::new("(x)*").unwrap().captures(&text).unwrap().get(1).map(|found| found.start), Some(19_999));
+    }
+}
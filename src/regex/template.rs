@@ -0,0 +1,202 @@
+use super::captures::Captures;
+
+/*
+ * Replacement text for Regex::replace, written the way sed's is: $1 or
+ * ${1} for what a group matched, $name or ${name} for a group written
+ * (?<name>, $0 for the whole match and $$ for a dollar sign, with \U and
+ * \L upper- or lowercasing everything after them up to the next \E.
+ */
+
+/**
+ * A parsed replacement template. Anything that is not a well formed group
+ * or case escape stands for itself.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    pieces: Vec<Piece>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Piece {
+    Text(String),
+    Group(usize),
+    Named(String),
+    Case(Case),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Case {
+    Upper,
+    Lower,
+    Unchanged,
+}
+
+impl Template {
+    pub fn new(template: &str) -> Template {
+        let mut pieces = vec![];
+        let mut text = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            let piece = match (c, chars.peek()) {
+                ('$', Some('$')) => {
+                    chars.next();
+                    text.push('$');
+                    continue;
+                }
+                ('$', Some('{')) => {
+                    let ahead: String = chars.clone().skip(1).take_while(|c| *c != '}').collect();
+                    if !is_reference(&ahead) || chars.clone().nth(1 + ahead.chars().count()) != Some('}') {
+                        text.push(c);
+                        continue;
+                    }
+                    for _ in 0..ahead.chars().count() + 2 {
+                        chars.next();
+                    }
+                    reference(ahead)
+                }
+                ('$', Some(next)) if is_name_char(*next) => {
+                    let mut name = String::new();
+                    while let Some(c) = chars.peek().cloned().filter(|c| is_name_char(*c)) {
+                        name.push(c);
+                        chars.next();
+                    }
+                    reference(name)
+                }
+                ('\\', Some(escape @ ('U' | 'L' | 'E'))) => {
+                    let case = match escape {
+                        'U' => Case::Upper,
+                        'L' => Case::Lower,
+                        _ => Case::Unchanged,
+                    };
+                    chars.next();
+                    Piece::Case(case)
+                }
+                ('\\', Some('\\')) => {
+                    chars.next();
+                    text.push('\\');
+                    continue;
+                }
+                (c, _) => {
+                    text.push(c);
+                    continue;
+                }
+            };
+            if !text.is_empty() {
+                pieces.push(Piece::Text(std::mem::take(&mut text)));
+            }
+            pieces.push(piece);
+        }
+        if !text.is_empty() {
+            pieces.push(Piece::Text(text));
+        }
+        Template { pieces }
+    }
+
+    /**
+     * The groups the template refers to, by number or by name, that a
+     * pattern with the given number of groups and group names lacks.
+     */
+    pub(super) fn missing(&self, count: usize, names: &[(String, usize)]) -> Vec<String> {
+        self.pieces
+            .iter()
+            .filter_map(|piece| match piece {
+                Piece::Group(i) if *i > count => Some(i.to_string()),
+                Piece::Named(name) if !names.iter().any(|(group, _)| group == name) => Some(name.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /**
+     * Whether filling the template in needs to know what groups matched,
+     * rather than only the whole match.
+     */
+    pub(super) fn uses_groups(&self) -> bool {
+        self.pieces.iter().any(|piece| match piece {
+            Piece::Group(i) => *i > 0,
+            Piece::Named(_) => true,
+            _ => false,
+        })
+    }
+
+    /**
+     * Fill the template in for one match, adding the result to out. Groups
+     * that took no part in the match, or do not exist, add nothing.
+     */
+    pub(super) fn expand(&self, captures: &Captures, out: &mut String) {
+        let mut case = Case::Unchanged;
+        for piece in &self.pieces {
+            let text = match piece {
+                Piece::Text(text) => text.as_str(),
+                Piece::Group(i) => captures.get(*i).map_or("", |found| found.as_str()),
+                Piece::Named(name) => captures.name(name).map_or("", |found| found.as_str()),
+                Piece::Case(next) => {
+                    case = *next;
+                    continue;
+                }
+            };
+            match case {
+                Case::Upper => out.push_str(&text.to_uppercase()),
+                Case::Lower => out.push_str(&text.to_lowercase()),
+                Case::Unchanged => out.push_str(text),
+            }
+        }
+    }
+}
+
+fn is_name_char(c: char) -> bool {
+    c == '_' || c.is_ascii_alphanumeric()
+}
+
+fn is_reference(text: &str) -> bool {
+    !text.is_empty() && text.chars().all(is_name_char)
+}
+
+// a group by number if the reference is all digits, and by name otherwise
+fn reference(name: String) -> Piece {
+    match name.parse() {
+        Ok(i) => Piece::Group(i),
+        Err(_) => Piece::Named(name),
+    }
+}
+
+#[cfg(test)]
+mod template_tests {
+    use crate::regex::Regex;
+
+    fn replaced(pattern: &str, text: &str, template: &str) -> String {
+        Regex::new(pattern).unwrap().replace_all(text, template)
+    }
+
+    #[test]
+    fn groups() {
+        assert_eq!(replaced("([a-z]+)=([0-9]+)", "a=1 b=22", "$2:$1"), "1:a 22:b");
+        assert_eq!(replaced("[0-9]+", "a1b22", "<$0>"), "a<1>b<22>");
+        assert_eq!(replaced("(a)(b)", "ab", "${1}1$2"), "a1b");
+        assert_eq!(replaced("(?<key>[a-z]+)=(?<value>[0-9]+)", "x=5", "$value is ${key}"), "5 is x");
+        assert_eq!(replaced("(a)|(b)", "ab", "[$2]"), "[][b]");
+    }
+
+    #[test]
+    fn literal_text() {
+        assert_eq!(replaced("a", "a", "$$1 \\\\ ${ $"), "$1 \\ ${ $");
+        assert_eq!(replaced("a", "a", "\\n${x y}"), "\\n${x y}");
+        assert_eq!(replaced("a", "a", "$9"), "");
+    }
+
+    #[test]
+    fn case_conversion() {
+        assert_eq!(replaced("([a-z]+)\\ ([a-z]+)", "hello world", "\\U$1\\E $2"), "HELLO world");
+        assert_eq!(replaced("[A-Za-z]+", "MiXeD", "\\L$0!"), "mixed!");
+        assert_eq!(replaced("(é)", "é", "\\U${1}x\\Ly"), "ÉXy");
+    }
+
+    #[test]
+    fn first_and_every_match() {
+        let regex = Regex::new("o").unwrap();
+        assert_eq!(regex.replace("foo", "0"), "f0o");
+        assert_eq!(regex.replace_all("foo", "0"), "f00");
+        assert_eq!(regex.replace_all("bar", "0"), "bar");
+        assert_eq!(Regex::new("x*").unwrap().replace_all("ab", "-"), "-a-b-");
+    }
+}
@@ -1,7 +1,8 @@
+use std::fmt;
 use std::iter::Peekable;
 use std::str::Chars;
 
-/**
+/*
  * thegrep - Tar Heel egrep
  *
  * Author: Sarah Bost, Shannon Goad
@@ -18,29 +19,144 @@ use std::str::Chars;
 #[derive(Debug, PartialEq)]
 pub enum Token {
     Char(char),
+    Class(Vec<(char, char)>),
     UnionBar,
     AnyChar,
     KleeneStar,
     KleenePlus,
     LParen,
+    // a left paren written (?<name> to give the group a name
+    NamedLParen(String),
     RParen,
+    Intersection,
+    Complement,
+    // only lexed by an extended tokenizer, since a plain dash is so often literal
+    Difference,
+}
+
+/**
+ * Whether a char would be lexed as something other than a Char token, and so
+ * must be written with a backslash to be matched literally. A dash is only
+ * an operator to an extended tokenizer, but is escaped so that the text
+ * means the same to both.
+ */
+pub fn needs_escape(c: char) -> bool {
+    matches!(
+        c,
+        '|' | '*' | '+' | '.' | '(' | ')' | '[' | '\\' | ' ' | '\t' | '\n' | '&' | '~' | '-'
+    )
+}
+
+/**
+ * Whether a char must be written with a backslash inside a [class].
+ */
+pub fn needs_class_escape(c: char) -> bool {
+    matches!(c, ']' | '-' | '\\')
+}
+
+/**
+ * Tokens display as the pattern text they are lexed from.
+ */
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Char(c) if needs_escape(*c) => write!(f, "\\{}", c),
+            Token::Char(c) => write!(f, "{}", c),
+            Token::Class(ranges) => write_class(f, ranges),
+            Token::UnionBar => write!(f, "|"),
+            Token::AnyChar => write!(f, "."),
+            Token::KleeneStar => write!(f, "*"),
+            Token::KleenePlus => write!(f, "+"),
+            Token::LParen => write!(f, "("),
+            Token::NamedLParen(name) => write!(f, "(?<{}>", name),
+            Token::RParen => write!(f, ")"),
+            Token::Intersection => write!(f, "&"),
+            Token::Complement => write!(f, "~"),
+            Token::Difference => write!(f, "-"),
+        }
+    }
+}
+
+/**
+ * Writes ranges of chars as a bracketed [class].
+ */
+pub fn write_class(f: &mut fmt::Formatter, ranges: &[(char, char)]) -> fmt::Result {
+    let write_char = |f: &mut fmt::Formatter, c: char| {
+        if needs_class_escape(c) {
+            write!(f, "\\{}", c)
+        } else {
+            write!(f, "{}", c)
+        }
+    };
+    write!(f, "[")?;
+    for (start, end) in ranges {
+        write_char(f, *start)?;
+        if *end as u32 == *start as u32 + 1 {
+            write_char(f, *end)?;
+        } else if start != end {
+            write!(f, "-")?;
+            write_char(f, *end)?;
+        }
+    }
+    write!(f, "]")
+}
+
+/**
+ * A byte range of the input, from start up to but not including end.
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
 }
 
 /**
  * The internal state of a Tokenizer is maintained by a peekable character
- * iterator over a &str's Chars.
+ * iterator over a &str's Chars, along with the byte offset it has reached.
  */
 pub struct Tokenizer<'str> {
     chars: Peekable<Chars<'str>>,
+    offset: usize,
+    // whether a dash outside of a class is the difference operator
+    extended: bool,
 }
 
 // this creates a new tokenizer from input given as argument, this is what is called in main.rs
 impl<'str> Tokenizer<'str> {
-    pub fn new(input: &'str str) -> Tokenizer {
+    pub fn new(input: &'str str) -> Tokenizer<'str> {
         Tokenizer {
             chars: input.chars().peekable(),
+            offset: 0,
+            extended: false,
+        }
+    }
+
+    /**
+     * A tokenizer for the extended syntax, where a dash outside of a class
+     * is the difference operator rather than a char.
+     */
+    pub fn extended(input: &'str str) -> Tokenizer<'str> {
+        Tokenizer {
+            extended: true,
+            ..Tokenizer::new(input)
         }
     }
+
+    /**
+     * Like `next`, but also returns the span of input the token was lexed from.
+     */
+    pub fn next_spanned(&mut self) -> Option<(Token, Span)> {
+        self.lex_whitespace();
+        let start = self.offset;
+        let token = self.next()?;
+        Some((
+            token,
+            Span {
+                start,
+                end: self.offset,
+            },
+        ))
+    }
 }
 
 /**
@@ -56,19 +172,21 @@ impl<'str> Iterator for Tokenizer<'str> {
      */
     fn next(&mut self) -> Option<Token> {
         self.lex_whitespace();
-        if let Some(c) = self.chars.peek() {
-            Some(match c {
-                '|' => self.lex_union_bar(),
-                '*' => self.lex_kleene_star(),
-                '.' => self.lex_any_char(),
-                '(' | ')' => self.lex_paren(),
-                '+' => self.lex_kleene_plus(),
-                _ => self.lex_char(),
-                // these match options should allow whitespace to be recognized as a char token
-            })
-        } else {
-            None
-        }
+        let c = *self.chars.peek()?;
+        Some(match c {
+            '|' => self.lex_union_bar(),
+            '*' => self.lex_kleene_star(),
+            '.' => self.lex_any_char(),
+            '(' | ')' => self.lex_paren(),
+            '+' => self.lex_kleene_plus(),
+            '\\' => self.lex_escape(),
+            '[' => self.lex_class(),
+            '&' => self.lex_operator(Token::Intersection),
+            '~' => self.lex_operator(Token::Complement),
+            '-' if self.extended => self.lex_operator(Token::Difference),
+            _ => self.lex_char(),
+            // these match options should allow whitespace to be recognized as a char token
+        })
     }
 }
 
@@ -77,18 +195,25 @@ impl<'str> Iterator for Tokenizer<'str> {
  * so these are internal methods only.
  */
 impl<'str> Tokenizer<'str> {
+    // consumes a single char, keeping track of how far into the input we are
+    fn bump(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        self.offset += c.len_utf8();
+        Some(c)
+    }
+
     // consumes whitespace
     fn lex_whitespace(&mut self) {
         while let Some(c) = self.chars.peek() {
             match c {
-                ' ' | '\t' | '\n' => self.chars.next(),
+                ' ' | '\t' | '\n' => self.bump(),
                 _ => break,
             };
         }
     }
     // consumes char, which will be union bar, and returns a unionbar token
     fn lex_union_bar(&mut self) -> Token {
-        let c = self.chars.next().unwrap();
+        let c = self.bump().unwrap();
         match c {
             '|' => Token::UnionBar,
             _ => panic!("unknown char"),
@@ -97,7 +222,7 @@ impl<'str> Tokenizer<'str> {
 
     // consumes char, which will be kleene, and returns a kleenestar token
     fn lex_kleene_star(&mut self) -> Token {
-        let c = self.chars.next().unwrap();
+        let c = self.bump().unwrap();
         match c {
             '*' => Token::KleeneStar,
             _ => panic!("Unexpected char"),
@@ -106,7 +231,7 @@ impl<'str> Tokenizer<'str> {
 
     //consumers kleene plus and returns KleenePlus token
     fn lex_kleene_plus(&mut self) -> Token {
-        let c = self.chars.next().unwrap();
+        let c = self.bump().unwrap();
         match c {
             '+' => Token::KleenePlus,
             _ => panic!("Unexpected char"),
@@ -115,7 +240,13 @@ impl<'str> Tokenizer<'str> {
 
     // consumes char, which will be paren, and returns a paren token
     fn lex_paren(&mut self) -> Token {
-        let c = self.chars.next().unwrap();
+        if let Some(name) = self.group_name() {
+            for _ in 0..name.chars().count() + 4 {
+                self.bump();
+            }
+            return Token::NamedLParen(name);
+        }
+        let c = self.bump().unwrap();
         match c {
             '(' => Token::LParen,
             ')' => Token::RParen,
@@ -123,15 +254,96 @@ impl<'str> Tokenizer<'str> {
         }
     }
 
+    // looks ahead from a left paren for a name written as (?<name>, where the name is letters,
+    // digits and underscores not starting with a digit; anything else after the paren is lexed
+    // as chars, the way it always was
+    fn group_name(&self) -> Option<String> {
+        let mut ahead = self.chars.clone();
+        if ahead.next() != Some('(') || ahead.next() != Some('?') || ahead.next() != Some('<') {
+            return None;
+        }
+        let mut name = String::new();
+        for c in ahead {
+            match c {
+                '>' if !name.is_empty() => return Some(name),
+                c if c == '_' || c.is_ascii_alphabetic() || (c.is_ascii_digit() && !name.is_empty()) => name.push(c),
+                _ => return None,
+            }
+        }
+        None
+    }
+
+    // consumes the one char an operator is written with and returns its token
+    fn lex_operator(&mut self, token: Token) -> Token {
+        self.bump();
+        token
+    }
+
     // consumes char and returns a char token
     fn lex_char(&mut self) -> Token {
-        let c = self.chars.next().unwrap();
+        let c = self.bump().unwrap();
         Token::Char(c)
     }
 
+    // consumes a backslash and returns the char after it as a plain char token, so that
+    // operators and whitespace can be matched literally; a trailing backslash is itself
+    fn lex_escape(&mut self) -> Token {
+        let c = self.bump().unwrap();
+        match self.bump() {
+            Some(escaped) => Token::Char(escaped),
+            None => Token::Char(c),
+        }
+    }
+
+    // consumes a bracketed class such as [a-z_] and returns a class token of its ranges;
+    // a bracket with no closing bracket after it is just a char
+    fn lex_class(&mut self) -> Token {
+        if !self.class_is_closed() {
+            return self.lex_char();
+        }
+        self.bump();
+        let mut ranges = vec![];
+        loop {
+            let start = match self.bump().unwrap() {
+                ']' => break,
+                '\\' => self.bump().unwrap(),
+                c => c,
+            };
+            // a dash is a range unless it is the last thing in the class
+            let mut ahead = self.chars.clone();
+            if ahead.next() == Some('-') && ahead.peek() != Some(&']') {
+                self.bump();
+                let end = match self.bump().unwrap() {
+                    '\\' => self.bump().unwrap(),
+                    c => c,
+                };
+                ranges.push((start.min(end), start.max(end)));
+            } else {
+                ranges.push((start, start));
+            }
+        }
+        Token::Class(ranges)
+    }
+
+    // looks ahead from an opening bracket for the unescaped bracket that closes it
+    fn class_is_closed(&self) -> bool {
+        let mut ahead = self.chars.clone();
+        ahead.next();
+        while let Some(c) = ahead.next() {
+            match c {
+                ']' => return true,
+                '\\' => {
+                    ahead.next();
+                }
+                _ => {}
+            }
+        }
+        false
+    }
+
     // consumes char, which will be anychar, and returns an anychar token
     fn lex_any_char(&mut self) -> Token {
-        let c = self.chars.next().unwrap();
+        let c = self.bump().unwrap();
         match c {
             '.' => Token::AnyChar,
             _ => panic!("unknown char"),
@@ -219,6 +431,87 @@ mod iterator {
         assert_eq!(tokens.next(), Some(Token::KleenePlus));
     }
 
+    #[test]
+    fn escapes() {
+        let mut tokens = Tokenizer::new("\\*\\\\\\ a\\");
+        assert_eq!(tokens.next(), Some(Token::Char('*')));
+        assert_eq!(tokens.next(), Some(Token::Char('\\')));
+        assert_eq!(tokens.next(), Some(Token::Char(' ')));
+        assert_eq!(tokens.next(), Some(Token::Char('a')));
+        assert_eq!(tokens.next(), Some(Token::Char('\\')));
+        assert_eq!(tokens.next(), None);
+    }
+
+    #[test]
+    fn spans() {
+        let mut tokens = Tokenizer::new("é \\| (");
+        assert_eq!(tokens.next_spanned(), Some((Token::Char('é'), Span { start: 0, end: 2 })));
+        assert_eq!(tokens.next_spanned(), Some((Token::Char('|'), Span { start: 3, end: 5 })));
+        assert_eq!(tokens.next_spanned(), Some((Token::LParen, Span { start: 6, end: 7 })));
+        assert_eq!(tokens.next_spanned(), None);
+    }
+
+    #[test]
+    fn classes() {
+        let mut tokens = Tokenizer::new("[a-z_\\]-][z-a-][x");
+        assert_eq!(tokens.next(), Some(Token::Class(vec![('a', 'z'), ('_', '_'), (']', ']'), ('-', '-')])));
+        assert_eq!(tokens.next(), Some(Token::Class(vec![('a', 'z'), ('-', '-')])));
+        assert_eq!(tokens.next(), Some(Token::Char('[')));
+        assert_eq!(tokens.next(), Some(Token::Char('x')));
+        assert_eq!(tokens.next(), None);
+    }
+
+    #[test]
+    fn literal_brackets() {
+        let mut tokens = Tokenizer::new("a[b]x\\[y]");
+        assert_eq!(tokens.next(), Some(Token::Char('a')));
+        assert_eq!(tokens.next(), Some(Token::Class(vec![('b', 'b')])));
+        assert_eq!(tokens.next(), Some(Token::Char('x')));
+        assert_eq!(tokens.next(), Some(Token::Char('[')));
+        assert_eq!(tokens.next(), Some(Token::Char('y')));
+        assert_eq!(tokens.next(), Some(Token::Char(']')));
+        assert_eq!(tokens.next(), None);
+
+        let mut tokens = Tokenizer::new("[\\]");
+        assert_eq!(tokens.next(), Some(Token::Char('[')));
+        assert_eq!(tokens.next(), Some(Token::Char(']')));
+        assert_eq!(tokens.next(), None);
+    }
+
+    #[test]
+    fn set_operators() {
+        let mut tokens = Tokenizer::new("a&~b-\\&");
+        assert_eq!(tokens.next(), Some(Token::Char('a')));
+        assert_eq!(tokens.next(), Some(Token::Intersection));
+        assert_eq!(tokens.next(), Some(Token::Complement));
+        assert_eq!(tokens.next(), Some(Token::Char('b')));
+        assert_eq!(tokens.next(), Some(Token::Char('-')));
+        assert_eq!(tokens.next(), Some(Token::Char('&')));
+        assert_eq!(tokens.next(), None);
+
+        let mut tokens = Tokenizer::extended("a-[a-b]\\-");
+        assert_eq!(tokens.next(), Some(Token::Char('a')));
+        assert_eq!(tokens.next(), Some(Token::Difference));
+        assert_eq!(tokens.next(), Some(Token::Class(vec![('a', 'b')])));
+        assert_eq!(tokens.next(), Some(Token::Char('-')));
+        assert_eq!(tokens.next(), None);
+    }
+
+    #[test]
+    fn named_groups() {
+        let mut tokens = Tokenizer::new("(?<year>a)(?<>(?<1x>");
+        assert_eq!(tokens.next_spanned(), Some((Token::NamedLParen("year".into()), Span { start: 0, end: 8 })));
+        assert_eq!(tokens.next(), Some(Token::Char('a')));
+        assert_eq!(tokens.next(), Some(Token::RParen));
+        assert_eq!(tokens.next(), Some(Token::LParen));
+        assert_eq!(tokens.next(), Some(Token::Char('?')));
+        assert_eq!(tokens.next(), Some(Token::Char('<')));
+        assert_eq!(tokens.next(), Some(Token::Char('>')));
+        assert_eq!(tokens.next(), Some(Token::LParen));
+        assert_eq!(tokens.next(), Some(Token::Char('?')));
+        assert_eq!(Token::NamedLParen("year".into()).to_string(), "(?<year>");
+    }
+
     #[test]
     fn basic_kleene_pluse() {
         let mut tokens = Tokenizer::new("(a)+");